@@ -0,0 +1,228 @@
+use crate::{AxisAnchor, AxisPlacement, ColorA, Options, Placement};
+
+// a set of property overrides that apply at a given frame. properties left as None are not
+// affected by this keyframe.
+//
+// between two keyframes that set the same property, its value is interpolated linearly (except
+// `delay`, which just switches over). before the first keyframe setting a property, it is
+// interpolated from the value in the base Options at frame 0, and after the last one it holds that
+// keyframe's value.
+#[derive(Clone, Debug, Default)]
+pub struct Keyframe {
+    pub frame: usize,
+    pub text_placement: Option<Placement>,
+    pub text_color: Option<ColorA>,
+    pub text_outline_color: Option<ColorA>,
+    // with `Options::bg_gradient` set, these are its first and last stops.
+    pub bg_top_color: Option<ColorA>,
+    pub bg_bottom_color: Option<ColorA>,
+    pub gloss_color: Option<ColorA>,
+    // (x, y), as fractions of the width and height like in Gloss
+    pub gloss_center: Option<(f32, f32)>,
+    pub stripe_phase: Option<usize>,
+    // how long frames starting from this one are displayed, in milliseconds
+    pub delay: Option<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Animation {
+    pub frame_count: usize,
+    // default frame duration in milliseconds, until a keyframe sets `delay`
+    pub frame_delay: u32,
+    // how many times to play the animation, 0 means forever.
+    pub loop_count: u32,
+    // need not be sorted.
+    pub keyframes: Vec<Keyframe>,
+}
+
+pub struct Frame {
+    pub delay: u32,
+    // same format as what `generate` returns.
+    pub data: Vec<u8>,
+}
+
+impl Animation {
+    pub fn new(frame_count: usize, frame_delay: u32) -> Self {
+        Self { frame_count, frame_delay, loop_count: 0, keyframes: Vec::new() }
+    }
+
+    fn track<T: Copy>(
+        &self,
+        frame: usize,
+        base: T,
+        get: impl Fn(&Keyframe) -> Option<T>,
+        lerp: impl Fn(T, T, f32) -> T,
+    ) -> T {
+        let mut prev = (0, base);
+        for kf in &self.keyframes {
+            let Some(v) = get(kf) else { continue };
+            if kf.frame > frame {
+                let t = (frame - prev.0) as f32 / (kf.frame - prev.0) as f32;
+                return lerp(prev.1, v, t);
+            }
+            prev = (kf.frame, v);
+        }
+        prev.1
+    }
+
+    // returns the options to use for the given frame.
+    pub fn frame_options(&self, opts: &Options, frame: usize) -> Options {
+        let mut anim = self.clone();
+        anim.keyframes.sort_by_key(|k| k.frame);
+        let mut o = opts.clone();
+        o.text_placement = anim.track(frame, o.text_placement, |k| k.text_placement, lerp_placement);
+        o.text_color = anim.track(frame, o.text_color, |k| k.text_color, lerp_color);
+        o.text_outline_color = anim.track(frame, o.text_outline_color, |k| k.text_outline_color, lerp_color);
+        o.bg_top_color = anim.track(frame, o.bg_top_color, |k| k.bg_top_color, lerp_color);
+        o.bg_bottom_color = anim.track(frame, o.bg_bottom_color, |k| k.bg_bottom_color, lerp_color);
+        if let Some(g) = o.bg_gradient.as_mut() {
+            if let Some(s) = g.stops.first_mut() {
+                s.color = anim.track(frame, s.color, |k| k.bg_top_color, lerp_color);
+            }
+            if let Some(s) = g.stops.last_mut() {
+                s.color = anim.track(frame, s.color, |k| k.bg_bottom_color, lerp_color);
+            }
+        }
+        if let Some(g) = o.gloss.as_mut() {
+            g.color = anim.track(frame, g.color, |k| k.gloss_color, lerp_color);
+            (g.center_x, g.center_y) = anim.track(
+                frame,
+                (g.center_x, g.center_y),
+                |k| k.gloss_center,
+                |a, b, t| (lerp(a.0, b.0, t), lerp(a.1, b.1, t)),
+            );
+        }
+        if let Some(s) = o.diag_stripes.as_mut() {
+            s.phase = anim.track(frame, s.phase, |k| k.stripe_phase, |a, b, t| {
+                lerp(a as f32, b as f32, t).round() as usize
+            });
+        }
+        o
+    }
+
+    pub fn frame_delay(&self, frame: usize) -> u32 {
+        self.keyframes
+            .iter()
+            .filter(|k| k.frame <= frame && k.delay.is_some())
+            .max_by_key(|k| k.frame)
+            .and_then(|k| k.delay)
+            .unwrap_or(self.frame_delay)
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn lerp_color(a: ColorA, b: ColorA, t: f32) -> ColorA {
+    let ch = |a: u8, b: u8| lerp(a as f32, b as f32, t).round() as u8;
+    ColorA(ch(a.0, b.0), ch(a.1, b.1), ch(a.2, b.2), ch(a.3, b.3))
+}
+
+fn lerp_placement(a: Placement, b: Placement, t: f32) -> Placement {
+    // offsets only make sense to interpolate if they're measured from the same edge
+    let axis = |a: AxisPlacement, b: AxisPlacement| {
+        if a.anchor == b.anchor {
+            AxisPlacement { anchor: a.anchor, offset: lerp(a.offset as f32, b.offset as f32, t).round() as isize }
+        } else if t < 1. {
+            a
+        } else {
+            b
+        }
+    };
+    Placement { horz: axis(a.horz, b.horz), vert: axis(a.vert, b.vert) }
+}
+
+pub fn generate_frames(opts: &Options, anim: &Animation) -> Vec<Frame> {
+    (0..anim.frame_count)
+        .map(|i| Frame { delay: anim.frame_delay(i), data: crate::generate(&anim.frame_options(opts, i)) })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarqueeMode {
+    // the text wraps around endlessly, with `gap` pixels between repeats.
+    Loop,
+    // the text scrolls until its other end is visible, then scrolls back. does nothing if the text
+    // already fits.
+    Bounce,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarqueeDirection {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Marquee {
+    pub mode: MarqueeMode,
+    pub direction: MarqueeDirection,
+    // pixels per frame
+    pub speed: usize,
+    // space between repeats of the text, in Loop mode
+    pub gap: usize,
+    // duration of each frame in milliseconds
+    pub frame_delay: u32,
+    // how long to hold still at the ends (in Bounce mode) or at the start of the loop (in Loop
+    // mode), in milliseconds.
+    pub pause: u32,
+}
+
+impl Marquee {
+    // distance between the text and the edge of the bar when it's at either end of its path.
+    const MARGIN: isize = 6;
+
+    // returns the options and animation for scrolling the text of `opts` horizontally. the
+    // vertical text placement is kept as is.
+    pub fn build(&self, opts: &Options) -> (Options, Animation) {
+        let mut opts = opts.clone();
+        let speed = self.speed.max(1);
+        let textw = opts.main_text().render(opts.font()).width as isize + 2;
+        let place_at = |x: isize| Placement {
+            horz: AxisPlacement { anchor: AxisAnchor::Start, offset: x },
+            vert: opts.text_placement.vert,
+        };
+        let moving = Keyframe { delay: Some(self.frame_delay), ..Default::default() };
+        let paused = Keyframe { delay: Some(self.pause.max(self.frame_delay)), ..Default::default() };
+        let mut anim = Animation::new(1, self.frame_delay);
+        match self.mode {
+            MarqueeMode::Loop => {
+                let period = textw - 2 + self.gap as isize;
+                // nothing to scroll
+                if period <= 0 {
+                    opts.text_placement = place_at(Self::MARGIN);
+                    return (opts, anim);
+                }
+                let dist = if self.direction == MarqueeDirection::Left { -period } else { period };
+                anim.frame_count = (period as usize).div_ceil(speed);
+                opts.text_repeat_gap = Some(self.gap);
+                anim.keyframes = vec![
+                    Keyframe { frame: 0, text_placement: Some(place_at(Self::MARGIN)), ..paused },
+                    Keyframe { frame: 1, ..moving },
+                    // one past the last frame, so that the last frame leads right back into the first
+                    Keyframe { frame: anim.frame_count, text_placement: Some(place_at(Self::MARGIN + dist)), ..moving },
+                ];
+            }
+            MarqueeMode::Bounce => {
+                let start = Self::MARGIN;
+                let end = opts.width as isize - Self::MARGIN - textw;
+                if end >= start {
+                    opts.text_placement = place_at(start);
+                    return (opts, anim);
+                }
+                let (from, to) = if self.direction == MarqueeDirection::Left { (start, end) } else { (end, start) };
+                let steps = ((start - end) as usize).div_ceil(speed);
+                anim.frame_count = steps * 2;
+                anim.keyframes = vec![
+                    Keyframe { frame: 0, text_placement: Some(place_at(from)), ..paused },
+                    Keyframe { frame: 1, ..moving },
+                    Keyframe { frame: steps, text_placement: Some(place_at(to)), ..paused },
+                    Keyframe { frame: steps + 1, ..moving },
+                    Keyframe { frame: steps * 2, text_placement: Some(place_at(from)), ..moving },
+                ];
+            }
+        }
+        (opts, anim)
+    }
+}
@@ -0,0 +1,174 @@
+// parser for the Glyph Bitmap Distribution Format (BDF), as used by X11 and most pixel font editors.
+// see https://adobe-type-tools.github.io/font-tech-notes/pdfs/5005.BDF_Spec.pdf
+use crate::font::{BitmapFont, Glyph, ParseError};
+use std::collections::HashMap;
+
+fn err<T>(msg: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError(msg.into()))
+}
+
+fn nums<const N: usize>(args: &str) -> Result<[isize; N], ParseError> {
+    let v: Vec<isize> = args
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .or(err(format!("expected numbers, got {args:?}")))?;
+    v.get(..N)
+        .and_then(|x| x.try_into().ok())
+        .ok_or(ParseError(format!("expected {N} numbers, got {args:?}")))
+}
+
+pub fn parse(src: &str) -> Result<BitmapFont, ParseError> {
+    let mut lines = src.lines().map(str::trim).filter(|l| !l.is_empty());
+    if !lines.next().is_some_and(|l| l.starts_with("STARTFONT")) {
+        return err("missing STARTFONT");
+    }
+    let mut font_bbox = None;
+    let mut ascent = None;
+    let mut descent = None;
+    let mut cap_height = None;
+    let mut default_char = None;
+    // can be given for the whole font, and overridden per glyph
+    let mut font_dwidth = 0;
+    let mut glyphs = HashMap::new();
+
+    while let Some(line) = lines.next() {
+        let (kw, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        match kw {
+            "FONTBOUNDINGBOX" => font_bbox = Some(nums::<4>(args)?),
+            "FONT_ASCENT" => ascent = Some(nums::<1>(args)?[0]),
+            "FONT_DESCENT" => descent = Some(nums::<1>(args)?[0]),
+            "CAP_HEIGHT" => cap_height = Some(nums::<1>(args)?[0]),
+            "DEFAULT_CHAR" => default_char = Some(nums::<1>(args)?[0]),
+            "DWIDTH" => font_dwidth = nums::<1>(args)?[0],
+            "STARTCHAR" => {
+                let mut encoding = -1;
+                let mut dwidth = font_dwidth;
+                let mut bbx = None;
+                loop {
+                    let Some(line) = lines.next() else { return err("unterminated STARTCHAR") };
+                    let (kw, args) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+                    match kw {
+                        "ENCODING" => encoding = nums::<1>(args)?[0],
+                        "DWIDTH" => dwidth = nums::<1>(args)?[0],
+                        "BBX" => bbx = Some(nums::<4>(args)?),
+                        "BITMAP" => break,
+                        "ENDCHAR" => return err("glyph without BITMAP"),
+                        _ => {}
+                    }
+                }
+                let Some([w, h, xoff, yoff]) = bbx.or(font_bbox) else { return err("glyph without BBX") };
+                let (w, h) = (w.max(0) as usize, h.max(0) as usize);
+                let mut data = Vec::with_capacity(w * h);
+                for line in lines.by_ref() {
+                    if line == "ENDCHAR" {
+                        break;
+                    }
+                    // rows are padded to a whole number of bytes, leftmost pixel is the MSB. this goes
+                    // digit by digit so that rows can be any width.
+                    let digits: Vec<u8> = line
+                        .chars()
+                        .map(|c| c.to_digit(16).map(|d| d as u8))
+                        .collect::<Option<_>>()
+                        .ok_or(ParseError(format!("bad bitmap row {line:?}")))?;
+                    let bit = |x: usize| digits.get(x / 4).map_or(0, |d| d >> (3 - x % 4) & 1);
+                    data.extend((0..w).map(|x| bit(x) * 255));
+                }
+                if data.len() != w * h {
+                    return err(format!("glyph {encoding} has wrong number of bitmap rows"));
+                }
+                // -1 means the glyph isn't in the standard encoding, so there's no way to use it
+                if encoding >= 0 {
+                    let glyph = Glyph {
+                        advance: dwidth.max(0) as usize,
+                        width: w,
+                        height: h,
+                        left: xoff,
+                        top: yoff + h as isize,
+                        data,
+                    };
+                    glyphs.insert(encoding as u32, glyph);
+                }
+            }
+            "ENDFONT" => break,
+            _ => {}
+        }
+    }
+
+    let bbox_ascent = font_bbox.map(|b| b[1] + b[3]);
+    let bbox_descent = font_bbox.map(|b| -b[3]);
+    let ascent = ascent.or(bbox_ascent).ok_or(ParseError("no FONT_ASCENT".into()))?.max(0) as usize;
+    let descent = descent.or(bbox_descent).ok_or(ParseError("no FONT_DESCENT".into()))?.max(0) as usize;
+    let cap_height = cap_height
+        .map(|x| x.max(0) as usize)
+        .or_else(|| crate::font::measure_cap_height(&glyphs))
+        .unwrap_or(ascent)
+        .min(ascent);
+    let default_char = default_char
+        .map(|x| x as u32)
+        .or(['?', ' '].into_iter().map(|c| c as u32).find(|c| glyphs.contains_key(c)))
+        .unwrap_or(0);
+    Ok(BitmapFont { glyphs, default_char, ascent, descent, cap_height })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Font;
+
+    const FONT: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 6 0 -2
+FONT_ASCENT 5
+FONT_DESCENT 2
+DEFAULT_CHAR 66
+DWIDTH 5 0
+CHARS 2
+STARTCHAR A
+ENCODING 65
+DWIDTH 6 0
+BBX 3 2 1 -1
+BITMAP
+A0
+40
+ENDCHAR
+STARTCHAR B
+ENCODING 66
+BBX 2 1 0 0
+BITMAP
+C0
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn glyph_placement() {
+        let font = parse(FONT).unwrap();
+        assert_eq!((font.ascent, font.descent, font.default_char), (5, 2, 66));
+        let a = font.glyph('A').unwrap();
+        assert_eq!((a.width, a.height, a.left, a.top, a.advance), (3, 2, 1, 1, 6));
+        assert_eq!(a.data, [255, 0, 255, 0, 255, 0]);
+    }
+
+    #[test]
+    fn dwidth_fallback() {
+        // B has no DWIDTH of its own, so it gets the font's
+        assert_eq!(parse(FONT).unwrap().glyph('B').unwrap().advance, 5);
+    }
+
+    #[test]
+    fn default_char_fallback() {
+        let font = parse(FONT).unwrap();
+        assert_eq!(font.glyph('Z').unwrap().data, [255, 255]);
+        // without DEFAULT_CHAR, '?' or space is used if there's one, and otherwise nothing
+        let font = parse(&FONT.replace("DEFAULT_CHAR 66\n", "").replace("ENCODING 66", "ENCODING 63")).unwrap();
+        assert_eq!(font.default_char, '?' as u32);
+        assert!(parse(&FONT.replace("DEFAULT_CHAR 66\n", "")).unwrap().glyph('Z').is_none());
+    }
+
+    #[test]
+    fn wide_rows() {
+        let row = format!("{}80", "F".repeat(32));
+        let src = FONT.replace("BBX 2 1 0 0\nBITMAP\nC0", &format!("BBX 129 1 0 0\nBITMAP\n{row}"));
+        assert_eq!(parse(&src).unwrap().glyph('B').unwrap().data, [255; 129]);
+    }
+}
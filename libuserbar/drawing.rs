@@ -1,6 +1,7 @@
 pub struct Canvas {
     width: usize,
     height: usize,
+    // RGBA, linear colorspace, premultiplied alpha. starts out fully transparent.
     data: Vec<f32>,
 }
 
@@ -28,8 +29,130 @@ impl ColorAF {
             a as f32 / 255.,
         )
     }
-    pub fn from_f32(r: f32, g: f32, b: f32) -> Self {
-        Self(r, g, b, 1.0)
+}
+impl ColorAF {
+    // mixes `a` and `b`, with `t` going from 0 (all a) to 1 (all b). like in CSS, the components
+    // (except hue) are mixed premultiplied by alpha, so that fading to transparent keeps the hue.
+    pub fn mix(a: crate::ColorA, b: crate::ColorA, t: f32, space: crate::ColorSpace, hue_path: crate::HuePath) -> Self {
+        let lerp = |x: f32, y: f32| x * (1. - t) + y * t;
+        let (alpha_a, alpha_b) = (a.3 as f32 / 255., b.3 as f32 / 255.);
+        let alpha = lerp(alpha_a, alpha_b);
+        let pmix = |x: f32, y: f32| if alpha > 0. { lerp(x * alpha_a, y * alpha_b) / alpha } else { lerp(x, y) };
+        let (la, lb) = (Self::from_srgb(a.0, a.1, a.2, 255), Self::from_srgb(b.0, b.1, b.2, 255));
+        let mixed = match space {
+            crate::ColorSpace::Srgb => {
+                let mix = |x: u8, y: u8| pmix(x as f32 / 255., y as f32 / 255.);
+                return Self::from_f_srgb(mix(a.0, b.0), mix(a.1, b.1), mix(a.2, b.2), alpha);
+            }
+            crate::ColorSpace::LinearRgb => [pmix(la.0, lb.0), pmix(la.1, lb.1), pmix(la.2, lb.2)],
+            crate::ColorSpace::Oklab => {
+                let (oa, ob) = (la.to_oklab(), lb.to_oklab());
+                from_oklab([pmix(oa[0], ob[0]), pmix(oa[1], ob[1]), pmix(oa[2], ob[2])])
+            }
+            crate::ColorSpace::Oklch => {
+                let ([l1, c1, h1], [l2, c2, h2]) = (to_lch(la.to_oklab()), to_lch(lb.to_oklab()));
+                // greys have no real hue, so take the other color's one
+                let (h1, h2) = match (c1 < 1e-4, c2 < 1e-4) {
+                    (true, false) => (h2, h2),
+                    (false, true) => (h1, h1),
+                    _ => (h1, h2),
+                };
+                let (h1, h2) = hue_pair(h1, h2, hue_path);
+                let h = lerp(h1, h2).to_radians();
+                let c = pmix(c1, c2);
+                from_oklab([pmix(l1, l2), c * h.cos(), c * h.sin()])
+            }
+        };
+        // colors in between can fall slightly outside of what sRGB can show
+        let [r, g, b] = mixed.map(|x| x.clamp(0., 1.));
+        Self(r, g, b, alpha)
+    }
+
+    // https://bottosson.github.io/posts/oklab/
+    fn to_oklab(self) -> [f32; 3] {
+        let (r, g, b) = (self.0 as f64, self.1 as f64, self.2 as f64);
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        [
+            0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        ]
+        .map(|x| x as f32)
+    }
+}
+
+// returns linear RGB.
+fn from_oklab(lab: [f32; 3]) -> [f32; 3] {
+    let [l, a, b] = lab.map(|x| x as f64);
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+    .map(|x| x as f32)
+}
+
+// lab to lightness, chroma, hue in degrees.
+fn to_lch([l, a, b]: [f32; 3]) -> [f32; 3] {
+    [l, a.hypot(b), b.atan2(a).to_degrees().rem_euclid(360.)]
+}
+
+// adjusts two hues so that going from one to the other linearly takes the right path.
+fn hue_pair(mut h1: f32, mut h2: f32, path: crate::HuePath) -> (f32, f32) {
+    let d = h2 - h1;
+    match path {
+        crate::HuePath::Shorter if d > 180. => h1 += 360.,
+        crate::HuePath::Shorter if d < -180. => h2 += 360.,
+        crate::HuePath::Longer if 0. < d && d < 180. => h1 += 360.,
+        crate::HuePath::Longer if -180. < d && d <= 0. => h2 += 360.,
+        crate::HuePath::Increasing if h2 < h1 => h2 += 360.,
+        crate::HuePath::Decreasing if h1 < h2 => h1 += 360.,
+        _ => {}
+    }
+    (h1, h2)
+}
+
+impl ColorAF {
+    pub fn scale_alpha(self, f: f32) -> Self {
+        Self(self.0, self.1, self.2, self.3 * f)
+    }
+    pub fn premultiplied(self) -> [f32; 4] {
+        [self.0 * self.3, self.1 * self.3, self.2 * self.3, self.3]
+    }
+
+    // applies the filters in the order they're listed in ImageFilter.
+    pub fn filtered(self, f: &crate::ImageFilter) -> Self {
+        let luminance = |c: Self| 0.2126 * c.0 + 0.7152 * c.1 + 0.0722 * c.2;
+        let mut c = self;
+        if let Some(g) = f.colorize.as_ref().filter(|g| !g.stops.is_empty()) {
+            // going by perceived lightness, so that midtones land in the middle of the gradient
+            let l = luminance(c).clamp(0., 1.).powf(1. / 2.2);
+            c = gradient_color(g, l).scale_alpha(c.3);
+        }
+        let gray = luminance(c);
+        let tint = f.tint.map(|t| ColorAF::from_srgb(t.0, t.1, t.2, t.3));
+        let encode = |x: f32| if x <= 0.0031308 { x * 12.92 } else { 1.055 * x.powf(1. / 2.4) - 0.055 };
+        let decode = |x: f32| if x <= 0.04045 { x / 12.92 } else { ((x + 0.055) / 1.055).powf(2.4) };
+        let channel = |x: f32, t: Option<f32>| {
+            let mut x = gray + (x - gray) * f.saturation;
+            if let (Some(t), Some(tint)) = (t, tint) {
+                x += (t - x) * tint.3;
+            }
+            // on sRGB values, so that contrast pivots on what looks like 50% gray
+            let x = encode(x.clamp(0., 1.));
+            decode(((x * f.brightness - 0.5) * f.contrast + 0.5).clamp(0., 1.))
+        };
+        Self(
+            channel(c.0, tint.map(|t| t.0)),
+            channel(c.1, tint.map(|t| t.1)),
+            channel(c.2, tint.map(|t| t.2)),
+            c.3 * f.opacity.clamp(0., 1.),
+        )
     }
 }
 impl std::ops::Add for ColorAF {
@@ -52,108 +175,154 @@ impl std::ops::Mul<f32> for ColorAF {
     }
 }
 
+// the color at position t along a gradient. it must have at least one stop.
+pub fn gradient_color(g: &crate::Gradient, t: f32) -> ColorAF {
+    let (first, last) = (&g.stops[0], &g.stops[g.stops.len() - 1]);
+    // find the stops on either side of t, and how far between them it is
+    let (a, b, frac) = match g.stops.iter().position(|s| s.position > t) {
+        None => (last, last, 0.),
+        Some(0) => (first, first, 0.),
+        Some(i) => {
+            let (a, b) = (&g.stops[i - 1], &g.stops[i]);
+            (a, b, (t - a.position) / (b.position - a.position))
+        }
+    };
+    ColorAF::mix(a.color, b.color, frac, g.space, g.hue_path)
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Self {
         Self {
             width,
             height,
-            data: vec![0.; width * height * 3],
+            data: vec![0.; width * height * 4],
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // source-over compositing of a non-premultiplied color onto the canvas.
     pub fn draw_px(&mut self, x: usize, y: usize, c: ColorAF) {
-        let ind = (y * self.width + x) * 3;
-        let col = ColorAF::from_f32(self.data[ind], self.data[ind + 1], self.data[ind + 2]);
+        let ind = (y * self.width + x) * 4;
+        let px = &mut self.data[ind..ind + 4];
+        let col = ColorAF(px[0], px[1], px[2], px[3]);
         let old_alpha = 1. - c.3;
         let new_alpha = c.3;
-        let new_col = (col * old_alpha) + c * new_alpha;
-        self.data[ind + 0] = new_col.0;
-        self.data[ind + 1] = new_col.1;
-        self.data[ind + 2] = new_col.2;
+        let new_col = (col * old_alpha) + ColorAF(c.0 * new_alpha, c.1 * new_alpha, c.2 * new_alpha, new_alpha);
+        px.copy_from_slice(&[new_col.0, new_col.1, new_col.2, new_col.3]);
     }
 
-    pub fn horz_line(&mut self, start: usize, end: usize, y: usize, c: ColorAF) {
-        for i in start..=end {
-            self.draw_px(i, y, c)
+    // composites another canvas onto this one, with its opacity scaled by `opacity`. see
+    // https://www.w3.org/TR/compositing-1/#blending
+    pub fn composite(&mut self, other: &Canvas, opacity: f32, mode: crate::layer::BlendMode) {
+        use crate::layer::BlendMode;
+        let blend = |cb: f32, cs: f32| match mode {
+            BlendMode::Normal => cs,
+            BlendMode::Multiply => cb * cs,
+            BlendMode::Screen => cb + cs - cb * cs,
+            BlendMode::Overlay if cb <= 0.5 => cs * 2. * cb,
+            BlendMode::Overlay => {
+                let cb = 2. * cb - 1.;
+                cs + cb - cs * cb
+            }
+            BlendMode::SoftLight if cs <= 0.5 => cb - (1. - 2. * cs) * cb * (1. - cb),
+            BlendMode::SoftLight => {
+                let d = if cb <= 0.25 { ((16. * cb - 12.) * cb + 4.) * cb } else { cb.sqrt() };
+                cb + (2. * cs - 1.) * (d - cb)
+            }
+            BlendMode::Add => (cb + cs).min(1.),
+            BlendMode::Difference => (cb - cs).abs(),
+        };
+        for (dst, src) in self.data.chunks_exact_mut(4).zip(other.data.chunks_exact(4)) {
+            let (ab, as_) = (dst[3], src[3] * opacity);
+            if as_ == 0. {
+                continue;
+            }
+            for i in 0..3 {
+                // un-premultiplied colors
+                let cb = if ab > 0. { dst[i] / ab } else { 0. };
+                let cs = src[i] / src[3];
+                dst[i] = as_ * (1. - ab) * cs + ab * (1. - as_) * cb + as_ * ab * blend(cb, cs);
+            }
+            dst[3] = as_ + ab * (1. - as_);
         }
     }
-    pub fn vert_line(&mut self, start: usize, end: usize, x: usize, c: ColorAF) {
-        for i in start..=end {
-            self.draw_px(x, i, c)
+
+    // scales the pixel's opacity by `coverage`, e.g. to cut out a shape.
+    pub fn mask_px(&mut self, x: usize, y: usize, coverage: f32) {
+        let ind = (y * self.width + x) * 4;
+        for v in &mut self.data[ind..ind + 4] {
+            *v *= coverage;
         }
     }
 
-    pub fn vert_gradient(
-        &mut self,
-        startx: usize,
-        endx: usize,
-        starty: usize,
-        endy: usize,
-        startc: crate::Color,
-        endc: crate::Color,
-    ) {
-        for y in starty..=endy {
-            // how much of the start color and how much of the end color to take
-            let denom = endy - starty;
-            let frac_e = (y - starty) as f32 / denom as f32;
-            let frac_s = 1. - frac_e;
-            // this could use oklab or whatever Fancy Color Space in theory
-            let mix = |x: u8, y: u8| x as f32 / 255. * frac_s + y as f32 / 255. * frac_e;
-            let col = ColorAF::from_f_srgb(
-                mix(startc.0, endc.0),
-                mix(startc.1, endc.1),
-                mix(startc.2, endc.2),
-                1.,
-            );
-
-            self.horz_line(startx, endx, y, col);
+    // fills the whole canvas with a gradient.
+    pub fn gradient(&mut self, g: &crate::Gradient) {
+        if g.stops.is_empty() {
+            return;
+        }
+        // coordinates are between the centers of the edge pixels, so that the first and last
+        // rows/columns get exactly the end colors
+        let (w, h) = ((self.width as f32 - 1.).max(0.), (self.height as f32 - 1.).max(0.));
+        // snap to 0 so that e.g. 180deg doesn't get skewed by float error
+        let snap = |x: f32| if x.abs() < 1e-6 { 0. } else { x };
+        let (sin, cos) = g.angle.to_radians().sin_cos();
+        let (sin, cos) = (snap(sin), snap(cos));
+        // position along the gradient line, which starts and ends at the corners
+        let project = |x: f32, y: f32| x * sin - y * cos;
+        let corners = [project(0., 0.), project(w, 0.), project(0., h), project(w, h)];
+        let start = corners.into_iter().fold(f32::INFINITY, f32::min);
+        let len = corners.into_iter().fold(f32::NEG_INFINITY, f32::max) - start;
+        let (cx, cy) = (g.center_x * w, g.center_y * h);
+        let (rx, ry) = {
+            let (fx, fy) = (cx.max(w - cx), cy.max(h - cy));
+            if g.circle {
+                let r = fx.hypot(fy);
+                (r, r)
+            } else {
+                // an ellipse through the farthest corner, proportional to the farthest sides
+                (fx * std::f32::consts::SQRT_2, fy * std::f32::consts::SQRT_2)
+            }
+        };
+        for (x, y) in itertools::iproduct!(0..self.width, 0..self.height) {
+            let (fx, fy) = (x as f32, y as f32);
+            let t = match g.kind {
+                crate::GradientKind::Linear if len == 0. => 0.,
+                crate::GradientKind::Linear => (project(fx, fy) - start) / len,
+                crate::GradientKind::Radial => {
+                    let dx = if rx == 0. { 0. } else { (fx - cx) / rx };
+                    let dy = if ry == 0. { 0. } else { (fy - cy) / ry };
+                    dx.hypot(dy)
+                }
+            };
+            self.draw_px(x, y, gradient_color(g, t));
         }
     }
 
-    pub fn ellipse(&mut self, centerx: f32, centery: f32, a: f32, b: f32, col: ColorAF) {
-        let a2 = (a * a).recip();
-        let b2 = (b * b).recip();
+    // draws `col` with its alpha scaled by `alpha_at(x, y)`, antialiased by taking 7x7 samples in
+    // each pixel.
+    pub fn shape(&mut self, col: ColorAF, alpha_at: impl Fn(f32, f32) -> f32) {
         for x in 0..self.width {
             for y in 0..self.height {
-                let dx = (x as f32) - centerx;
-                let dy = (y as f32) - centery;
-                // optimization to not do subsampling except when on the edge
-                // which quadrant are we in?
-                let off_x = if dx < 0. { 1 } else { -1 };
-                let off_y = if dy < 0. { 1 } else { -1 };
-                let outer_corner_x = dx - (3 * off_x) as f32 / 7.;
-                let outer_corner_y = dy - (3 * off_y) as f32 / 7.;
-                // if we are fully inside the ellipse:
-                if outer_corner_x.powi(2) * a2 + outer_corner_y.powi(2) * b2 <= 1. {
-                    self.draw_px(x, y, col);
-                    continue;
-                }
-                let inner_corner_x = dx + (3 * off_x) as f32 / 7.;
-                let inner_corner_y = dy + (3 * off_y) as f32 / 7.;
-                // if we are fully outside the ellipse:
-                if inner_corner_x.powi(2) * a2 + inner_corner_y.powi(2) * b2 > 1. {
-                    continue;
-                }
-
-                // otherwise, subsample.
-                let mut num_inside = 0_usize;
+                let mut total = 0.;
                 for sub_x in -3..=3 {
                     for sub_y in -3..=3 {
-                        let nx = dx + sub_x as f32 / 7.;
-                        let ny = dy + sub_y as f32 / 7.;
-                        if nx * nx * a2 + ny * ny * b2 <= 1. {
-                            num_inside += 1;
-                        }
+                        total += alpha_at(x as f32 + sub_x as f32 / 7., y as f32 + sub_y as f32 / 7.);
                     }
                 }
-                let new_alpha = num_inside as f32 / 49.;
-                let mut new_col = col;
-                new_col.3 *= new_alpha;
-                self.draw_px(x, y, new_col);
+                if total > 0. {
+                    self.draw_px(x, y, col.scale_alpha(total / 49.));
+                }
             }
         }
     }
 
+    // returns flat RGBA buffer (not premultiplied).
     pub fn get_buf(self) -> Vec<u8> {
         fn degamma(x: f32) -> u8 {
             let corrected = if x <= 0.0031308 {
@@ -164,6 +333,20 @@ impl Canvas {
             // let corrected = x; // linear for testing
             (corrected * 255.) as u8
         }
-        self.data.into_iter().map(degamma).collect()
+        self.data
+            .chunks_exact(4)
+            .flat_map(|px| {
+                let alpha = (px[3] * 255.).round().clamp(0., 255.) as u8;
+                // pixels that end up opaque are left alone so that float error in the alpha
+                // doesn't nudge their color
+                let unmul = |x: f32| if alpha == 255 || alpha == 0 { x } else { x / px[3] };
+                [
+                    degamma(unmul(px[0])),
+                    degamma(unmul(px[1])),
+                    degamma(unmul(px[2])),
+                    alpha,
+                ]
+            })
+            .collect()
     }
 }
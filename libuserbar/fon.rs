@@ -0,0 +1,131 @@
+// parser for Windows bitmap fonts: raw .FNT resources (versions 2 and 3), and NE-format .FON files,
+// which are 16-bit executables containing one or more .FNT resources.
+// based on dewinfont from https://github.com/juanitogan/mkwinfont
+use crate::font::{BitmapFont, Glyph, ParseError};
+use std::collections::HashMap;
+
+fn err<T>(msg: impl Into<String>) -> Result<T, ParseError> {
+    Err(ParseError(msg.into()))
+}
+
+fn byte(data: &[u8], off: usize) -> Result<u8, ParseError> {
+    data.get(off).copied().ok_or(ParseError(format!("truncated (reading offset {off:#x})")))
+}
+fn word(data: &[u8], off: usize) -> Result<u16, ParseError> {
+    Ok(u16::from_le_bytes([byte(data, off)?, byte(data, off + 1)?]))
+}
+fn dword(data: &[u8], off: usize) -> Result<u32, ParseError> {
+    Ok(word(data, off)? as u32 | (word(data, off + 2)? as u32) << 16)
+}
+
+// windows-1252 differs from latin-1 only in 0x80..0xa0.
+fn cp1252(b: u8) -> Option<char> {
+    const HIGH: [u16; 32] = [
+        0x20ac, 0, 0x201a, 0x0192, 0x201e, 0x2026, 0x2020, 0x2021, 0x02c6, 0x2030, 0x0160, 0x2039, 0x0152, 0, 0x017d, 0,
+        0, 0x2018, 0x2019, 0x201c, 0x201d, 0x2022, 0x2013, 0x2014, 0x02dc, 0x2122, 0x0161, 0x203a, 0x0153, 0, 0x017e, 0x0178,
+    ];
+    match b {
+        0x80..=0x9f => char::from_u32(HIGH[b as usize - 0x80] as u32).filter(|&c| c != '\0'),
+        _ => Some(b as char),
+    }
+}
+
+// parses a single .FNT resource.
+pub fn parse_fnt(fnt: &[u8]) -> Result<BitmapFont, ParseError> {
+    let version = word(fnt, 0)?;
+    let (ctstart, ctsize) = match version {
+        0x200 => (0x76, 4),
+        0x300 => (0x94, 6),
+        v => return err(format!("unsupported FNT version {v:#x}")),
+    };
+    if word(fnt, 0x42)? & 1 != 0 {
+        return err("vector fonts aren't supported");
+    }
+    let ascent = word(fnt, 0x4a)? as usize;
+    let height = word(fnt, 0x58)? as usize;
+    // ANSI is the only charset that maps cleanly to unicode. anything else is treated as latin-1.
+    let ansi = byte(fnt, 0x55)? == 0;
+    let first_char = byte(fnt, 0x5f)?;
+    let last_char = byte(fnt, 0x60)?;
+    let default_char = first_char.wrapping_add(byte(fnt, 0x61)?);
+    let to_char = |b: u8| if ansi { cp1252(b) } else { Some(b as char) };
+
+    let mut glyphs = HashMap::new();
+    for c in first_char..=last_char {
+        let entry = ctstart + ctsize * (c - first_char) as usize;
+        let width = word(fnt, entry)? as usize;
+        let offset = if ctsize == 4 { word(fnt, entry + 2)? as usize } else { dword(fnt, entry + 2)? as usize };
+        let Some(c) = to_char(c) else { continue };
+        if width == 0 {
+            continue;
+        }
+        // the bitmap is stored as columns of 8 pixels wide, each `height` bytes tall.
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let b = byte(fnt, offset + (x / 8) * height + y)?;
+                data.push(((b >> (7 - x % 8)) & 1) * 255);
+            }
+        }
+        glyphs.insert(c as u32, Glyph { advance: width, width, height, left: 0, top: ascent as isize, data });
+    }
+
+    // many fonts (Visitor included) bake the spacing between letters into the glyphs as a blank
+    // column on the right. trim it, so that it doesn't count towards the width of the text.
+    let blank_right = |g: &Glyph| g.data.chunks_exact(g.width).all(|row| row[g.width - 1] == 0);
+    if glyphs.values().all(blank_right) {
+        for g in glyphs.values_mut() {
+            g.data = g.data.chunks_exact(g.width).flat_map(|row| &row[..g.width - 1]).copied().collect();
+            g.width -= 1;
+        }
+    }
+
+    let cap_height = crate::font::measure_cap_height(&glyphs).unwrap_or(ascent);
+    Ok(BitmapFont {
+        glyphs,
+        default_char: to_char(default_char).unwrap_or(' ') as u32,
+        ascent,
+        descent: height.saturating_sub(ascent),
+        cap_height,
+    })
+}
+
+// returns all the fonts in an NE-format .FON file.
+pub fn parse_fon(fon: &[u8]) -> Result<Vec<BitmapFont>, ParseError> {
+    if fon.get(0..2) != Some(b"MZ") {
+        return err("MZ signature not found");
+    }
+    let neoff = dword(fon, 0x3c)? as usize;
+    match fon.get(neoff..neoff + 4) {
+        Some([b'N', b'E', ..]) => {}
+        Some(b"PE\0\0") => return err("PE-format .FON files aren't supported"),
+        _ => return err("NE signature not found"),
+    }
+    let rtable = neoff + word(fon, neoff + 0x24)? as usize;
+    let shift = word(fon, rtable)?;
+    let mut p = rtable + 2;
+    let mut fonts = Vec::new();
+    loop {
+        let rtype = word(fon, p)?;
+        if rtype == 0 {
+            break;
+        }
+        let count = word(fon, p + 2)?;
+        p += 8;
+        for _ in 0..count {
+            let start = (word(fon, p)? as usize).checked_shl(shift as u32);
+            let size = (word(fon, p + 2)? as usize).checked_shl(shift as u32);
+            // RT_FONT
+            if rtype == 0x8008 {
+                let fnt = start.zip(size).and_then(|(start, size)| fon.get(start..start.checked_add(size)?));
+                let Some(fnt) = fnt else { return err("resource overruns file") };
+                fonts.push(parse_fnt(fnt)?);
+            }
+            p += 12;
+        }
+    }
+    if fonts.is_empty() {
+        return err("no fonts in .FON file");
+    }
+    Ok(fonts)
+}
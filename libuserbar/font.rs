@@ -1,19 +1,227 @@
 use crate::font_data;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
-// renders the given text. returns in a somewhat weird format:
-// vec of columns. in each column values are from top down, 0=no text 1=yes text
-pub fn render(text: &str) -> Vec<[u8; 9]> {
-    let mut out: Vec<[u8; 9]> = Vec::new();
-    let default_char = font_data::FONT[&0x7f];
-    for c in text.chars() {
-        let char = font_data::FONT.get(&(c as u32)).unwrap_or(&default_char);
-        for col in 0..char.0 - 1 {
-            let real_col = char.0 - col - 2;
-            out.push(char.1.map(|x| ((x >> real_col) & 1) as u8));
+pub use crate::truetype::TrueTypeFont;
+
+#[derive(Debug)]
+pub struct ParseError(pub String);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "invalid font: {}", self.0)
+    }
+}
+impl std::error::Error for ParseError {}
+
+#[derive(Clone, Debug)]
+pub struct Glyph {
+    // how far to move right after drawing this glyph
+    pub advance: usize,
+    pub width: usize,
+    pub height: usize,
+    // position of the bitmap's top left corner relative to the pen position on the baseline. `top`
+    // counts upwards, so a glyph sitting on the baseline has top == height.
+    pub left: isize,
+    pub top: isize,
+    // row-major coverage, 0=no text 255=yes text
+    pub data: Vec<u8>,
+}
+
+// all metrics are in pixels.
+pub trait Font: std::fmt::Debug {
+    // returns the glyph for `c`, or the font's replacement glyph if it doesn't have one. None means
+    // the character should be skipped entirely.
+    fn glyph(&self, c: char) -> Option<Cow<'_, Glyph>>;
+    // how far to move right after drawing `c`
+    fn advance(&self, c: char) -> usize {
+        self.glyph(c).map_or(0, |g| g.advance)
+    }
+    // space above and below the baseline
+    fn ascent(&self) -> usize;
+    fn descent(&self) -> usize;
+    // height of capital letters above the baseline. text is centered vertically based on this
+    // rather than the full ascent, so it looks right in a 19px bar.
+    fn cap_height(&self) -> usize;
+    // distance from the top of a line to its baseline
+    fn baseline(&self) -> usize {
+        self.ascent()
+    }
+    fn line_height(&self) -> usize {
+        self.ascent() + self.descent()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BitmapFont {
+    pub glyphs: HashMap<u32, Glyph>,
+    // used for characters that aren't in `glyphs`
+    pub default_char: u32,
+    pub ascent: usize,
+    pub descent: usize,
+    pub cap_height: usize,
+}
+
+impl BitmapFont {
+    // the built-in Visitor font.
+    pub fn visitor() -> &'static BitmapFont {
+        static VISITOR: OnceLock<BitmapFont> = OnceLock::new();
+        VISITOR.get_or_init(|| {
+            let glyphs = font_data::FONT
+                .entries()
+                .map(|(&c, &(w, rows))| {
+                    // the rightmost column of each glyph is always empty, so it isn't stored
+                    let width = w as usize - 1;
+                    let data = rows
+                        .iter()
+                        .flat_map(|row| (0..width).map(move |col| ((row >> (width - 1 - col)) & 1) * 255))
+                        .collect();
+                    (c, Glyph { advance: w as usize, width, height: 9, left: 0, top: 7, data })
+                })
+                .collect();
+            BitmapFont { glyphs, default_char: 0x7f, ascent: 7, descent: 2, cap_height: 5 }
+        })
+    }
+
+    // loads a BDF, .FON or .FNT font, guessing the format from the contents. for .FON files
+    // containing multiple fonts, the first one is used.
+    pub fn from_bytes(data: &[u8]) -> Result<BitmapFont, ParseError> {
+        if data.starts_with(b"MZ") {
+            Ok(crate::fon::parse_fon(data)?.swap_remove(0))
+        } else if data.starts_with(b"STARTFONT") {
+            Self::from_bdf(std::str::from_utf8(data).or(Err(ParseError("BDF file isn't valid UTF-8".into())))?)
+        } else {
+            crate::fon::parse_fnt(data)
+        }
+    }
+
+    pub fn from_bdf(src: &str) -> Result<BitmapFont, ParseError> {
+        crate::bdf::parse(src)
+    }
+
+}
+
+impl Font for BitmapFont {
+    fn glyph(&self, c: char) -> Option<Cow<'_, Glyph>> {
+        self.glyphs.get(&(c as u32)).or_else(|| self.glyphs.get(&self.default_char)).map(Cow::Borrowed)
+    }
+    fn ascent(&self) -> usize {
+        self.ascent
+    }
+    fn descent(&self) -> usize {
+        self.descent
+    }
+    fn cap_height(&self) -> usize {
+        self.cap_height
+    }
+}
+
+// the font used when none is specified.
+pub fn default_font() -> &'static dyn Font {
+    BitmapFont::visitor()
+}
+
+// finds the height of capital letters by measuring the topmost inked row of X, since glyph bitmaps
+// may be padded.
+pub(crate) fn measure_cap_height(glyphs: &HashMap<u32, Glyph>) -> Option<usize> {
+    let g = glyphs.get(&('X' as u32))?;
+    let first_row = g.data.chunks_exact(g.width.max(1)).position(|row| row.iter().any(|&x| x != 0))?;
+    Some((g.top - first_row as isize).max(0) as usize)
+}
+
+pub struct TextBitmap {
+    pub width: usize,
+    pub height: usize,
+    // rows above the baseline. this is the font's baseline, unless some glyph sticks out above the
+    // ascent.
+    pub baseline: usize,
+    // row-major coverage, 0=no text 255=yes text
+    data: Vec<u8>,
+    // for each pixel, the index of the run of text that covers it the most
+    runs: Vec<usize>,
+}
+
+impl TextBitmap {
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.data[y * self.width + x]
+    }
+    pub fn run(&self, x: usize, y: usize) -> usize {
+        self.runs[y * self.width + x]
+    }
+
+    // grows the text by up to `radius` pixels, in the directions (dx, dy) for which `shape`
+    // returns true. this is used to make outlines. the result is 2*radius wider and taller, so its
+    // (0, 0) is at (-radius, -radius) in this bitmap.
+    pub fn dilate(&self, radius: usize, shape: impl Fn(isize, isize) -> bool) -> TextBitmap {
+        let (width, height) = (self.width + 2 * radius, self.height + 2 * radius);
+        let r = radius as isize;
+        let kernel: Vec<_> = itertools::iproduct!(-r..=r, -r..=r).filter(|&(dx, dy)| shape(dx, dy)).collect();
+        let mut data = vec![0; width * height];
+        let mut runs = vec![0; width * height];
+        for (x, y) in itertools::iproduct!(0..self.width, 0..self.height) {
+            let cov = self.get(x, y);
+            if cov == 0 {
+                continue;
+            }
+            for &(dx, dy) in &kernel {
+                let i = (y as isize + dy + r) as usize * width + (x as isize + dx + r) as usize;
+                if cov > data[i] {
+                    data[i] = cov;
+                    runs[i] = self.run(x, y);
+                }
+            }
+        }
+        TextBitmap { width, height, baseline: self.baseline + radius, data, runs }
+    }
+}
+
+// renders the given text. the bitmap covers at least the font's line height, and is extended to
+// fit any glyphs that go beyond it. the width is snug around the glyphs' bitmaps, ignoring the
+// spacing after the last character.
+pub fn render(font: &dyn Font, text: &str) -> TextBitmap {
+    render_runs(font, &[text])
+}
+
+// renders several pieces of text right after each other, keeping track of which pixel belongs to
+// which piece.
+pub fn render_runs(font: &dyn Font, runs: &[&str]) -> TextBitmap {
+    let glyphs: Vec<_> = runs
+        .iter()
+        .enumerate()
+        .flat_map(|(i, text)| text.chars().filter_map(move |c| Some((i, font.glyph(c)?))))
+        .collect();
+    let mut pen = 0;
+    let mut width = 0;
+    let mut above = font.baseline() as isize;
+    let mut below = font.line_height() as isize - font.baseline() as isize;
+    for (_, g) in &glyphs {
+        width = width.max(pen + g.left + g.width as isize);
+        above = above.max(g.top);
+        below = below.max(g.height as isize - g.top);
+        pen += g.advance as isize;
+    }
+    let width = width.max(0) as usize;
+    let height = (above + below) as usize;
+    let mut data = vec![0; width * height];
+    let mut run_idx = vec![0; width * height];
+    let mut pen = 0;
+    for (run, g) in glyphs {
+        for (gy, row) in g.data.chunks_exact(g.width.max(1)).enumerate() {
+            let y = above - g.top + gy as isize;
+            for (gx, &px) in row.iter().enumerate() {
+                let x = pen + g.left + gx as isize;
+                if x < 0 {
+                    continue;
+                }
+                let i = y as usize * width + x as usize;
+                if px > data[i] {
+                    data[i] = px;
+                    run_idx[i] = run;
+                }
+            }
         }
-        out.push([0; 9]);
+        pen += g.advance as isize;
     }
-    // remove the padding after the last character
-    out.pop();
-    out
+    TextBitmap { width, height, baseline: above as usize, data, runs: run_idx }
 }
@@ -0,0 +1,345 @@
+// the userbar is drawn as a stack of layers, from the bottom up. Options::layers builds the classic
+// stack, but any layers can be drawn in any order, any number of times.
+use crate::drawing::{Canvas, ColorAF};
+use crate::font::{self, Font};
+use crate::{AxisAnchor, AxisPlacement, BgImage, Border, BorderStyle, ColorA, Gloss, Gradient, Icon, OutlineShape, RepeatMode, StripePattern, TextItem};
+use wasm_bindgen::prelude::*;
+
+// how a layer's colors combine with what's under it. these work like the CSS blend modes, but in
+// linear light.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlendMode {
+    Normal,
+    // darkens, e.g. black stays black and white leaves the colors below as is.
+    Multiply,
+    // lightens, the opposite of multiply.
+    Screen,
+    // multiplies dark parts and screens light parts of what's below, increasing contrast.
+    Overlay,
+    SoftLight,
+    Add,
+    Difference,
+}
+
+#[derive(Clone, Debug)]
+pub enum LayerKind {
+    // fills the whole image.
+    Gradient(Gradient),
+    Pattern(StripePattern),
+    Image(BgImage),
+    // an image repeated across the whole image, with one copy at its placement. this is the same as
+    // Image with RepeatMode::Repeat, unless the image has some other repeat mode set.
+    Tile(BgImage),
+    Gloss(Gloss),
+    Text(TextItem),
+    Icon(Icon),
+    // the border, following corners rounded by the given radius.
+    Border(Border, f32),
+    // cuts off everything below outside a rectangle with corners rounded by the given radius.
+    RoundCorners(f32),
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Debug)]
+pub struct Layer {
+    #[wasm_bindgen(skip)]
+    pub kind: LayerKind,
+    // 0 to 1. the layer is drawn by itself first, so that e.g. text and its outline fade out
+    // together.
+    pub opacity: f32,
+    pub blend: BlendMode,
+}
+
+#[wasm_bindgen]
+impl Layer {
+    pub fn gradient(g: Gradient) -> Layer {
+        Layer::new(LayerKind::Gradient(g))
+    }
+    pub fn pattern(p: StripePattern) -> Layer {
+        Layer::new(LayerKind::Pattern(p))
+    }
+    pub fn image(img: BgImage) -> Layer {
+        Layer::new(LayerKind::Image(img))
+    }
+    pub fn tile(img: BgImage) -> Layer {
+        Layer::new(LayerKind::Tile(img))
+    }
+    pub fn gloss(g: Gloss) -> Layer {
+        Layer::new(LayerKind::Gloss(g))
+    }
+    pub fn text(item: TextItem) -> Layer {
+        Layer::new(LayerKind::Text(item))
+    }
+    pub fn icon(icon: Icon) -> Layer {
+        Layer::new(LayerKind::Icon(icon))
+    }
+    pub fn border(border: Border, corner_radius: f32) -> Layer {
+        Layer::new(LayerKind::Border(border, corner_radius))
+    }
+    pub fn round_corners(radius: f32) -> Layer {
+        Layer::new(LayerKind::RoundCorners(radius))
+    }
+}
+
+impl Layer {
+    pub fn new(kind: LayerKind) -> Layer {
+        Layer { kind, opacity: 1., blend: BlendMode::Normal }
+    }
+
+    pub fn with_blend(self, blend: BlendMode) -> Layer {
+        Layer { blend, ..self }
+    }
+}
+
+// draws the layers onto a transparent width x height image. returns the same format as
+// `generate`.
+pub fn render(width: usize, height: usize, font: &dyn Font, layers: &[Layer]) -> Vec<u8> {
+    let mut canvas = Canvas::new(width, height);
+    for layer in layers {
+        let opacity = layer.opacity.clamp(0., 1.);
+        if let LayerKind::RoundCorners(r) = layer.kind {
+            // this works on what's already drawn, so it can't be drawn separately
+            clip_corners(&mut canvas, r, opacity);
+        } else if opacity >= 1. && layer.blend == BlendMode::Normal {
+            draw_layer(&mut canvas, font, &layer.kind);
+        } else if opacity > 0. {
+            let mut tmp = Canvas::new(width, height);
+            draw_layer(&mut tmp, font, &layer.kind);
+            canvas.composite(&tmp, opacity, layer.blend);
+        }
+    }
+    canvas.get_buf()
+}
+
+fn draw_layer(canvas: &mut Canvas, font: &dyn Font, kind: &LayerKind) {
+    let (width, height) = (canvas.width(), canvas.height());
+    match kind {
+        LayerKind::Gradient(g) => canvas.gradient(g),
+        LayerKind::Pattern(pattern) => {
+            let color = to_af_color(pattern.color);
+            for x in 0..width as isize {
+                for y in 0..height as isize {
+                    if pattern.covers(x, y) {
+                        canvas.draw_px(x as usize, y as usize, color);
+                    }
+                }
+            }
+        }
+        LayerKind::Image(img) => draw_image(canvas, img, img.repeat),
+        LayerKind::Tile(tile) => {
+            let repeat = if tile.repeat == RepeatMode::None { RepeatMode::Repeat } else { tile.repeat };
+            draw_image(canvas, tile, repeat)
+        }
+        LayerKind::Gloss(gloss) => {
+            canvas.shape(to_af_color(gloss.color), |x, y| gloss.alpha_at(x, y, width, height));
+        }
+        LayerKind::Text(item) => draw_text(canvas, font, item),
+        LayerKind::Icon(icon) => draw_icon(canvas, icon),
+        LayerKind::Border(border, r) => draw_border(canvas, border, *r),
+        LayerKind::RoundCorners(r) => clip_corners(canvas, *r, 1.),
+    }
+}
+
+fn draw_image(canvas: &mut Canvas, img: &BgImage, repeat: RepeatMode) {
+    let img = img.fitted(canvas.width(), canvas.height());
+    draw_fitted_image(canvas, &img, repeat);
+}
+
+// draws an image that's already been resized to the canvas by `BgImage::fitted`.
+fn draw_fitted_image(canvas: &mut Canvas, img: &BgImage, repeat: RepeatMode) {
+    let (width, height) = (canvas.width(), canvas.height());
+    let (offx, offy) = img.offset(width, height);
+    let identity = img.filter.is_identity();
+    for x in 0..width as isize {
+        for y in 0..height as isize {
+            if let Some((ix, iy)) = img.source_pos(x - offx, y - offy, repeat) {
+                let c = to_af_color(img.pixel(ix, iy));
+                let c = if identity { c } else { c.filtered(&img.filter) };
+                canvas.draw_px(x as usize, y as usize, c.scale_alpha(img.fade.alpha_at(ix, iy, img.width, img.height)));
+            }
+        }
+    }
+}
+
+fn draw_icon(canvas: &mut Canvas, icon: &Icon) {
+    let (width, height) = (canvas.width(), canvas.height());
+    let img = icon.image.fitted(width, height);
+    // the outline covers each pixel as much as the most opaque pixel of the icon within reach
+    if icon.outline.shape != OutlineShape::None {
+        let (offx, offy) = img.offset(width, height);
+        let (offx, offy) = (offx + icon.outline.offset_x, offy + icon.outline.offset_y);
+        let r = icon.outline.radius as isize;
+        let kernel: Vec<_> = itertools::iproduct!(-r..=r, -r..=r).filter(|&(dx, dy)| icon.outline.contains(dx, dy)).collect();
+        let alpha = |x: usize, y: usize| {
+            img.pixel(x, y).3 as f32 / 255. * img.fade.alpha_at(x, y, img.width, img.height) * img.filter.opacity
+        };
+        let color = to_af_color(icon.outline_color);
+        for (x, y) in itertools::iproduct!(0..width as isize, 0..height as isize) {
+            let coverage = kernel
+                .iter()
+                .filter_map(|&(dx, dy)| img.source_pos(x - offx - dx, y - offy - dy, RepeatMode::None))
+                .map(|(ix, iy)| alpha(ix, iy))
+                .fold(0., f32::max);
+            if coverage > 0. {
+                canvas.draw_px(x as usize, y as usize, color.scale_alpha(coverage));
+            }
+        }
+    }
+    draw_fitted_image(canvas, &img, RepeatMode::None);
+}
+
+fn to_af_color(c: ColorA) -> ColorAF {
+    ColorAF::from_srgb(c.0, c.1, c.2, c.3)
+}
+
+// draws the bitmap's coverage with (offx, offy) being where its top left corner goes. each run of
+// text in the bitmap gets the color with the same index in `cols`.
+fn draw_coverage(canvas: &mut Canvas, bmp: &font::TextBitmap, offx: isize, offy: isize, cols: &[ColorAF]) {
+    let (width, height) = (canvas.width() as isize, canvas.height() as isize);
+    for (x, y) in itertools::iproduct!(0..bmp.width, 0..bmp.height) {
+        let cov = bmp.get(x, y);
+        let cx = offx + x as isize;
+        let cy = offy + y as isize;
+        if cov != 0 && cx >= 0 && cx < width && cy >= 0 && cy < height {
+            canvas.draw_px(cx as usize, cy as usize, cols[bmp.run(x, y)].scale_alpha(cov as f32 / 255.));
+        }
+    }
+}
+
+fn draw_text(canvas: &mut Canvas, font: &dyn Font, item: &TextItem) {
+    let (width, height) = (canvas.width(), canvas.height());
+    let spans = item.spans();
+    let rendered = item.render(font);
+    // the text is laid out as if it had the default 1px outline, so that changing the outline doesn't
+    // move it around.
+    let textw = rendered.width + 2;
+    // +1 because this is the offset of the "main" text, but we computed it with the shadow
+    let text_horz_offset =
+        item.placement
+            .horz
+            .to_offset(AxisPlacement { anchor: AxisAnchor::End, offset: 6 }, textw as isize, width as isize)
+            + 1;
+    // the text is placed by the box around its capital letters plus the shadow, since most letters
+    // don't reach the full ascent or descent. so we have +1 for the shadow, and shift up by the
+    // part of the rendered text above the capitals
+    let text_vert_offset =
+        item.placement
+            .vert
+            .to_offset(AxisPlacement { anchor: AxisAnchor::Center, offset: 0 }, font.cap_height() as isize + 2, height as isize)
+            + 1
+            - (rendered.baseline as isize - font.cap_height() as isize);
+    let text_copies: Vec<isize> = match item.repeat_gap {
+        Some(gap) => {
+            let period = (rendered.width + gap).max(1) as isize;
+            // start one period early so the copy sliding in from the left is drawn too
+            let first = text_horz_offset.rem_euclid(period) - period;
+            (first..width as isize + 1).step_by(period as usize).collect()
+        }
+        None => vec![text_horz_offset],
+    };
+
+    // draw the shadow of the text first
+    if item.outline.shape != OutlineShape::None {
+        let outline = rendered.dilate(item.outline.radius, |dx, dy| item.outline.contains(dx, dy));
+        let outline_colors: Vec<_> = spans.iter().map(|s| to_af_color(s.outline_color.unwrap_or(item.outline_color))).collect();
+        let r = item.outline.radius as isize;
+        for &copy_x in &text_copies {
+            draw_coverage(
+                canvas,
+                &outline,
+                copy_x - r + item.outline.offset_x,
+                text_vert_offset - r + item.outline.offset_y,
+                &outline_colors,
+            );
+        }
+    }
+    // now draw the main text
+    let colors: Vec<_> = spans.iter().map(|s| to_af_color(s.color.unwrap_or(item.color))).collect();
+    for &copy_x in &text_copies {
+        draw_coverage(canvas, &rendered, copy_x, text_vert_offset, &colors);
+    }
+}
+
+// distance from (x, y) to the edge of a w x h rectangle with corners rounded by r, positive
+// inside.
+fn rounded_rect_dist(x: f32, y: f32, w: f32, h: f32, r: f32) -> f32 {
+    let qx = (r - x).max(x - (w - r)).max(0.);
+    let qy = (r - y).max(y - (h - r)).max(0.);
+    if qx > 0. && qy > 0. {
+        r - qx.hypot(qy)
+    } else {
+        x.min(y).min(w - x).min(h - y)
+    }
+}
+
+// counts how many of the 7x7 samples in pixel (x, y) are outside the corners, on the border, and on
+// the light and dark bevel.
+fn border_samples(canvas: &Canvas, border: Option<&Border>, corner_radius: f32, x: usize, y: usize) -> [usize; 4] {
+    let (w, h) = (canvas.width() as f32, canvas.height() as f32);
+    let r = corner_radius.clamp(0., w.min(h) / 2.);
+    let bw = border.map_or(0., |b| b.width as f32);
+    // how far in from the edge the border and bevel go
+    let inner = match border {
+        Some(b) if b.style == BorderStyle::Double => 3. * bw,
+        _ => bw,
+    };
+    let has_bevel = border.is_some_and(|b| b.bevel_light.is_some() || b.bevel_dark.is_some());
+    let reach = inner + if has_bevel { 1. } else { 0. };
+    let (cx, cy) = (x as f32 + 0.5, y as f32 + 0.5);
+    let mut counts = [0; 4];
+    // skip pixels that are well clear of the corners and the border
+    if rounded_rect_dist(cx, cy, w, h, r) > reach + 1. {
+        return counts;
+    }
+    for (sub_x, sub_y) in itertools::iproduct!(-3..=3, -3..=3) {
+        let (px, py) = (cx + sub_x as f32 / 7., cy + sub_y as f32 / 7.);
+        let d = rounded_rect_dist(px, py, w, h, r);
+        if d < 0. {
+            counts[0] += 1;
+            continue;
+        }
+        let Some(b) = border else { continue };
+        // which edge is closest, and the position along it
+        let horizontal_edge = py.min(h - py) < px.min(w - px);
+        let top_or_left = if horizontal_edge { py < h - py } else { px < w - px };
+        let along = if horizontal_edge { px } else { py };
+        let in_border = match b.style {
+            BorderStyle::Solid => d < bw,
+            BorderStyle::Double => d < bw || (2. * bw <= d && d < 3. * bw),
+            BorderStyle::Dashed => d < bw && ((along / b.dash_length.max(1) as f32) as usize).is_multiple_of(2),
+        };
+        if in_border {
+            counts[1] += 1;
+        } else if inner <= d && d < inner + 1. {
+            counts[if top_or_left { 2 } else { 3 }] += 1;
+        }
+    }
+    counts
+}
+
+// makes everything outside the rounded corners transparent (or less opaque, for opacity < 1),
+// antialiased.
+fn clip_corners(canvas: &mut Canvas, corner_radius: f32, opacity: f32) {
+    if corner_radius <= 0. {
+        return;
+    }
+    for (x, y) in itertools::iproduct!(0..canvas.width(), 0..canvas.height()) {
+        let [outside, ..] = border_samples(canvas, None, corner_radius, x, y);
+        if outside > 0 {
+            canvas.mask_px(x, y, 1. - outside as f32 / 49. * opacity);
+        }
+    }
+}
+
+// draws the border, antialiased by taking 7x7 samples in each pixel.
+fn draw_border(canvas: &mut Canvas, border: &Border, corner_radius: f32) {
+    for (x, y) in itertools::iproduct!(0..canvas.width(), 0..canvas.height()) {
+        let [_, on_border, light, dark] = border_samples(canvas, Some(border), corner_radius, x, y);
+        for (count, col) in [(light, border.bevel_light), (dark, border.bevel_dark), (on_border, Some(border.color))] {
+            if let Some(col) = col.filter(|_| count > 0) {
+                canvas.draw_px(x, y, to_af_color(col).scale_alpha(count as f32 / 49.));
+            }
+        }
+    }
+}
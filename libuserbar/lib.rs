@@ -1,10 +1,19 @@
+pub mod animation;
+mod bdf;
 mod drawing;
-mod font;
+mod fon;
+mod resample;
+pub mod font;
 mod font_data;
+pub mod layer;
+pub mod markup;
+mod truetype;
+use layer::{BlendMode, Layer};
+use std::sync::Arc;
 use wasm_bindgen::prelude::*;
 
 #[wasm_bindgen]
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum AxisAnchor {
     Auto,
     Center,
@@ -53,22 +62,332 @@ pub struct Color(pub u8, pub u8, pub u8);
 #[derive(Clone, Copy, Debug)]
 pub struct ColorA(pub u8, pub u8, pub u8, pub u8);
 
+impl ColorA {
+    // parses a hex color with 3, 4, 6 or 8 digits, optionally starting with #.
+    pub fn from_hex(hex: &str) -> Result<ColorA, String> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        let digits: Vec<u8> = hex
+            .chars()
+            .map(|c| c.to_digit(16).map(|x| x as u8))
+            .collect::<Option<_>>()
+            .ok_or(format!("Invalid hex color {hex:?}"))?;
+        match *digits.as_slice() {
+            [r, g, b] => Ok(ColorA(r * 0x11, g * 0x11, b * 0x11, 255)),
+            [r, g, b, a] => Ok(ColorA(r * 0x11, g * 0x11, b * 0x11, a * 0x11)),
+            [r1, r2, g1, g2, b1, b2] => Ok(ColorA(r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2, 255)),
+            [r1, r2, g1, g2, b1, b2, a1, a2] => Ok(ColorA(r1 << 4 | r2, g1 << 4 | g2, b1 << 4 | b2, a1 << 4 | a2)),
+            _ => Err("Expected RGBA hex color to have 3, 4, 6 or 8 digits".into()),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GradientKind {
+    // colors change along a line at `angle`.
+    Linear,
+    // colors change going outwards from `center_x`, `center_y`, in ellipses with the same
+    // proportions as the image (or circles, if `circle` is set).
+    Radial,
+}
+
+// color space to mix gradient colors in.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorSpace {
+    // mixes the gamma-encoded values, like the original generator did. simple, but the middle of
+    // e.g. blue to yellow comes out grey and dark.
+    Srgb,
+    LinearRgb,
+    // perceptually uniform, so the brightness changes evenly.
+    Oklab,
+    // Oklab as lightness, chroma and hue. colors in the middle keep their saturation, and the hue
+    // goes around the color wheel as set by `hue_path`.
+    Oklch,
+}
+
+// which way to go around the color wheel when mixing in Oklch. these work like in CSS.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HuePath {
+    Shorter,
+    Longer,
+    Increasing,
+    Decreasing,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct GradientStop {
+    // 0 is the start of the gradient, 1 is the end.
+    pub position: f32,
+    pub color: ColorA,
+}
+
+#[wasm_bindgen]
+impl GradientStop {
+    #[wasm_bindgen(constructor)]
+    pub fn new(position: f32, color: ColorA) -> Self {
+        Self { position, color }
+    }
+}
+
+// works like CSS's linear-gradient and radial-gradient, measured between pixel centers.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    // direction of a linear gradient in degrees, CSS style: 0 goes upwards, 90 to the right, 180
+    // downwards. the gradient spans the image from corner to corner in this direction.
+    pub angle: f32,
+    // center of a radial gradient, as a fraction of the width and height. the gradient ends at the
+    // farthest corner.
+    pub center_x: f32,
+    pub center_y: f32,
+    pub circle: bool,
+    pub space: ColorSpace,
+    pub hue_path: HuePath,
+    // must be sorted by position. before the first stop and after the last one, the color is
+    // that of the nearest stop.
+    pub stops: Vec<GradientStop>,
+}
+
+#[wasm_bindgen]
+impl Gradient {
+    // a linear gradient without any stops.
+    #[wasm_bindgen(constructor)]
+    pub fn new(angle: f32) -> Self {
+        Self {
+            kind: GradientKind::Linear,
+            angle,
+            center_x: 0.5,
+            center_y: 0.5,
+            circle: false,
+            space: ColorSpace::Srgb,
+            hue_path: HuePath::Shorter,
+            stops: Vec::new(),
+        }
+    }
+    // a radial gradient without any stops.
+    pub fn radial(center_x: f32, center_y: f32, circle: bool) -> Self {
+        Self { kind: GradientKind::Radial, center_x, center_y, circle, ..Self::new(0.) }
+    }
+    pub fn add_stop(&mut self, position: f32, color: ColorA) {
+        self.stops.push(GradientStop { position, color });
+    }
+}
+
+impl Gradient {
+    // the classic top-to-bottom gradient.
+    pub fn vertical(top: ColorA, bottom: ColorA) -> Self {
+        let mut g = Self::new(180.);
+        g.add_stop(0., top);
+        g.add_stop(1., bottom);
+        g
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlossShape {
+    // centered at `center_x`, `center_y` with radii `radius_x`, `radius_y`.
+    Ellipse,
+    // a straight band through `center_x`, `center_y`, tilted clockwise by `angle` degrees from
+    // horizontal, reaching `radius_y` (as a fraction of the height) to either side.
+    Band,
+}
+
+// how the gloss fades out from its middle to its edge.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlossFalloff {
+    Flat,
+    Linear,
+    // fades in an S-curve, so there's no visible edge at either end.
+    Smooth,
+}
+
+// the "glare" effect. positions and sizes are fractions of the width and height.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Gloss {
+    pub shape: GlossShape,
+    // color in the middle of the gloss.
+    pub color: ColorA,
+    pub falloff: GlossFalloff,
+    pub center_x: f32,
+    pub center_y: f32,
+    pub radius_x: f32,
+    pub radius_y: f32,
+    pub angle: f32,
+}
+
+#[wasm_bindgen]
+impl Gloss {
+    // the original ellipse covering the top half.
+    pub fn ellipse(color: ColorA) -> Self {
+        Self {
+            shape: GlossShape::Ellipse,
+            color,
+            falloff: GlossFalloff::Flat,
+            center_x: 0.5,
+            center_y: 0.,
+            radius_x: 0.5,
+            radius_y: 0.5,
+            angle: 0.,
+        }
+    }
+    // a straight highlight over the top half.
+    pub fn top_half(color: ColorA) -> Self {
+        Self { shape: GlossShape::Band, center_y: 0., radius_y: 0.5, ..Self::ellipse(color) }
+    }
+    // a slanted streak of light across the left part.
+    pub fn sweep(color: ColorA) -> Self {
+        Self {
+            shape: GlossShape::Band,
+            falloff: GlossFalloff::Smooth,
+            center_x: 0.25,
+            center_y: 0.5,
+            radius_y: 0.6,
+            angle: -60.,
+            ..Self::ellipse(color)
+        }
+    }
+    // fades from `color` at the top to nothing at the bottom.
+    pub fn vertical_gradient(color: ColorA) -> Self {
+        Self { shape: GlossShape::Band, falloff: GlossFalloff::Linear, center_y: 0., radius_y: 1., ..Self::ellipse(color) }
+    }
+}
+
+impl Gloss {
+    // how much of the color to draw at (x, y) in a width x height image, from 0 to 1.
+    fn alpha_at(&self, x: f32, y: f32, width: usize, height: usize) -> f32 {
+        let (w, h) = (width as f32, height as f32);
+        let (dx, dy) = (x - self.center_x * w, y - self.center_y * h);
+        // distance from the middle, where 1 is the edge
+        let d = match self.shape {
+            GlossShape::Ellipse => {
+                let (rx, ry) = (self.radius_x * w, self.radius_y * h);
+                (dx * dx / (rx * rx) + dy * dy / (ry * ry)).sqrt()
+            }
+            GlossShape::Band => {
+                let (sin, cos) = self.angle.to_radians().sin_cos();
+                (dy * cos - dx * sin).abs() / (self.radius_y * h)
+            }
+        };
+        // NaN if a radius is 0
+        if d.is_nan() || d > 1. {
+            return 0.;
+        }
+        match self.falloff {
+            GlossFalloff::Flat => 1.,
+            GlossFalloff::Linear => 1. - d,
+            GlossFalloff::Smooth => 1. - d * d * (3. - 2. * d),
+        }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BorderStyle {
+    Solid,
+    // two lines of `width`, with a gap of `width` between them.
+    Double,
+    // dashes and gaps of `dash_length` pixels.
+    Dashed,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Border {
+    pub color: ColorA,
+    pub style: BorderStyle,
+    pub width: usize,
+    pub dash_length: usize,
+    // 1px lines just inside the border, on the top and left side and on the bottom and right side,
+    // to make the bar look raised (or sunken, with the colors swapped).
+    pub bevel_light: Option<ColorA>,
+    pub bevel_dark: Option<ColorA>,
+}
+
+#[wasm_bindgen]
+impl Border {
+    #[wasm_bindgen(constructor)]
+    pub fn new(color: ColorA) -> Self {
+        Self { color, style: BorderStyle::Solid, width: 1, dash_length: 4, bevel_light: None, bevel_dark: None }
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PatternKind {
+    // stripes going from bottom left to top right, the original "scanlines".
+    Diagonal,
+    // stripes going from top left to bottom right.
+    AntiDiagonal,
+    Horizontal,
+    Vertical,
+    // both kinds of diagonal stripes.
+    Crosshatch,
+    // both horizontal and vertical stripes.
+    Grid,
+    // squares of `thickness` pixels, `spacing` apart in both directions.
+    Dots,
+    // alternating squares of `spacing` pixels. ignores `thickness`.
+    Checkerboard,
+}
+
 #[wasm_bindgen]
 #[derive(Clone, Copy, Debug)]
 pub struct StripePattern {
     // originally only black.
     pub color: ColorA,
-    pub on_main_diagonal: bool,
-    // width of the pattern (there are spacing-1 blank pixels between stripes).
+    pub kind: PatternKind,
+    // width of the pattern (there are spacing-thickness blank pixels between stripes).
     // original generator supported 3 or 4.
     pub spacing: usize,
+    // width of each stripe.
+    pub thickness: usize,
+    // moves the pattern by this many pixels: sideways for diagonal, vertical and crosshatch
+    // patterns, down for horizontal ones, and diagonally (along both axes) for grids, dots and
+    // checkerboards.
+    pub phase: usize,
 }
 
 #[wasm_bindgen]
 impl StripePattern {
+    // diagonal stripes, going from top left to bottom right if `on_main_diagonal` is set.
     #[wasm_bindgen(constructor)]
     pub fn new(color: ColorA, on_main_diagonal: bool, spacing: usize) -> Self {
-        Self { color, on_main_diagonal, spacing }
+        let kind = if on_main_diagonal { PatternKind::AntiDiagonal } else { PatternKind::Diagonal };
+        Self::with_kind(color, kind, spacing)
+    }
+    pub fn with_kind(color: ColorA, kind: PatternKind, spacing: usize) -> Self {
+        Self { color, kind, spacing, thickness: 1, phase: 0 }
+    }
+}
+
+impl StripePattern {
+    // whether the pattern is drawn at (x, y).
+    fn covers(&self, x: isize, y: isize) -> bool {
+        let spacing = self.spacing.max(1) as isize;
+        let stripe = |off: isize| off.rem_euclid(spacing) < self.thickness as isize;
+        let phase = self.phase as isize;
+        let (x, y) = match self.kind {
+            PatternKind::Horizontal => (x, y + phase),
+            PatternKind::Grid | PatternKind::Dots | PatternKind::Checkerboard => (x + phase, y + phase),
+            _ => (x + phase, y),
+        };
+        match self.kind {
+            PatternKind::Diagonal => stripe(x + y),
+            PatternKind::AntiDiagonal => stripe(x - y),
+            PatternKind::Horizontal => stripe(y),
+            PatternKind::Vertical => stripe(x),
+            PatternKind::Crosshatch => stripe(x + y) || stripe(x - y),
+            PatternKind::Grid => stripe(x) || stripe(y),
+            PatternKind::Dots => stripe(x) && stripe(y),
+            PatternKind::Checkerboard => (x.div_euclid(spacing) + y.div_euclid(spacing)) % 2 == 0,
+        }
     }
 }
 
@@ -79,36 +398,385 @@ pub struct BgImage {
     pub height: usize,
     // data must be bytes in RGBA order, 8 bits per channel.
     pub data: Vec<u8>,
-    // placement of image, defaults to top left
+    // placement of image, defaults to top left. this applies to the image after it's scaled.
     pub placement: Placement,
+    pub fit: FitMode,
+    pub resample: Resample,
+    // whether to repeat the image to fill the space around it. copies line up with the one at
+    // `placement`.
+    pub repeat: RepeatMode,
+    pub filter: ImageFilter,
+    pub fade: ImageFade,
+}
+
+// fades the edges of an image out to transparent. when the image repeats, each copy fades
+// separately.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImageFade {
+    // how many pixels it takes to fade in from each edge, linearly.
+    pub left: usize,
+    pub right: usize,
+    pub top: usize,
+    pub bottom: usize,
+    // from 0 to 1, how far from the edges towards the middle an oval vignette starts fading.
+    pub vignette: f32,
+}
+
+#[wasm_bindgen]
+impl ImageFade {
+    #[wasm_bindgen(constructor)]
+    pub fn new(left: usize, right: usize, top: usize, bottom: usize, vignette: f32) -> Self {
+        Self { left, right, top, bottom, vignette }
+    }
+}
+
+impl ImageFade {
+    // how much of the pixel at (x, y) in a width x height image is left, from 0 to 1.
+    fn alpha_at(&self, x: usize, y: usize, width: usize, height: usize) -> f32 {
+        // distance from the edge to the pixel's center, over the length of the fade
+        let edge = |dist: usize, len: usize| if len == 0 { 1. } else { ((dist as f32 + 0.5) / len as f32).min(1.) };
+        let mut alpha = edge(x, self.left)
+            * edge(width - 1 - x, self.right)
+            * edge(y, self.top)
+            * edge(height - 1 - y, self.bottom);
+        if self.vignette > 0. {
+            let dx = (x as f32 + 0.5) / width as f32 * 2. - 1.;
+            let dy = (y as f32 + 0.5) / height as f32 * 2. - 1.;
+            let t = ((dx.hypot(dy) - (1. - self.vignette)) / self.vignette).clamp(0., 1.);
+            alpha *= 1. - t * t * (3. - 2. * t);
+        }
+        alpha
+    }
+}
+
+// color adjustments for images. colorize, saturation and tint are done in linear light, while
+// brightness and contrast work on sRGB values like CSS's brightness() and contrast().
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct ImageFilter {
+    // replaces each pixel's color with the one at the same position in the gradient as the
+    // pixel's lightness, black being the first stop and white the last. only the gradient's stops
+    // and color space matter.
+    pub colorize: Option<Gradient>,
+    // 0 is grayscale, 1 leaves the colors alone and more than 1 makes them more vivid.
+    pub saturation: f32,
+    // mixes the tint's color in, as much as its alpha says.
+    pub tint: Option<ColorA>,
+    // multiplies the colors.
+    pub brightness: f32,
+    // scales the distance of the colors from 50% gray.
+    pub contrast: f32,
+    pub opacity: f32,
+}
+
+#[wasm_bindgen]
+impl ImageFilter {
+    // a filter that does nothing.
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self { colorize: None, saturation: 1., tint: None, brightness: 1., contrast: 1., opacity: 1. }
+    }
+}
+
+impl Default for ImageFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ImageFilter {
+    fn is_identity(&self) -> bool {
+        self.colorize.is_none()
+            && self.tint.is_none()
+            && [self.saturation, self.brightness, self.contrast, self.opacity] == [1.; 4]
+    }
 }
 
 #[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepeatMode {
+    None,
+    // repeat horizontally only.
+    RepeatX,
+    // repeat vertically only.
+    RepeatY,
+    Repeat,
+    // repeat on both axes, flipping every other copy so that the edges match up.
+    Mirror,
+}
+
+// how to scale an image to the userbar's size.
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FitMode {
+    // keep the original size.
+    None,
+    // as large as possible while still fitting entirely.
+    Contain,
+    // as small as possible while still covering the whole userbar, cropping off the rest.
+    Cover,
+    // exactly the userbar's size, not keeping the aspect ratio.
+    Stretch,
+    // as tall as the userbar, keeping the aspect ratio.
+    FitHeight,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Resample {
+    // keeps pixel art sharp.
+    Nearest,
+    Bilinear,
+    // sharper than bilinear for photos, but may ring around hard edges.
+    Lanczos,
+}
+
+#[wasm_bindgen]
+impl BgImage {
+    #[wasm_bindgen(constructor)]
+    pub fn new(width: usize, height: usize, data: Vec<u8>, placement: Placement, fit: FitMode, resample: Resample) -> Self {
+        Self { width, height, data, placement, fit, resample, repeat: RepeatMode::None, filter: ImageFilter::new(), fade: ImageFade::default() }
+    }
+}
+
 impl BgImage {
+    // the image scaled according to `fit` for a width x height canvas.
+    fn fitted(&self, width: usize, height: usize) -> std::borrow::Cow<'_, BgImage> {
+        let (new_w, new_h) = self.fitted_size(width, height);
+        if (new_w, new_h) == (self.width, self.height) {
+            return std::borrow::Cow::Borrowed(self);
+        }
+        std::borrow::Cow::Owned(BgImage {
+            width: new_w,
+            height: new_h,
+            data: resample::resize(&self.data, self.width, self.height, new_w, new_h, self.resample),
+            ..self.clone()
+        })
+    }
+
+    // the size of the image after scaling it according to `fit`.
+    fn fitted_size(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.width == 0 || self.height == 0 {
+            return (self.width, self.height);
+        }
+        let (sx, sy) = (width as f32 / self.width as f32, height as f32 / self.height as f32);
+        match self.fit {
+            FitMode::None => (self.width, self.height),
+            FitMode::Stretch => (width, height),
+            FitMode::Contain | FitMode::Cover | FitMode::FitHeight => {
+                let s = match self.fit {
+                    FitMode::Contain => sx.min(sy),
+                    FitMode::Cover => sx.max(sy),
+                    _ => sy,
+                };
+                let scale = |x: usize| ((x as f32 * s).round() as usize).max(1);
+                (scale(self.width), scale(self.height))
+            }
+        }
+    }
+
+    // where the image's top left corner goes in a width x height canvas.
+    fn offset(&self, width: usize, height: usize) -> (isize, isize) {
+        let start = AxisPlacement { anchor: AxisAnchor::Start, offset: 0 };
+        (
+            self.placement.horz.to_offset(start, self.width as isize, width as isize),
+            self.placement.vert.to_offset(start, self.height as isize, height as isize),
+        )
+    }
+
+    // the pixel of the image that ends up at (x, y) relative to its top left corner, if any.
+    fn source_pos(&self, x: isize, y: isize, repeat: RepeatMode) -> Option<(usize, usize)> {
+        fn axis(v: isize, len: usize, repeat: bool, mirror: bool) -> Option<usize> {
+            let len = len as isize;
+            if mirror {
+                let p = v.rem_euclid(2 * len);
+                Some((if p < len { p } else { 2 * len - 1 - p }) as usize)
+            } else if repeat {
+                Some(v.rem_euclid(len) as usize)
+            } else {
+                (0..len).contains(&v).then_some(v as usize)
+            }
+        }
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+        let mirror = repeat == RepeatMode::Mirror;
+        let rx = matches!(repeat, RepeatMode::RepeatX | RepeatMode::Repeat);
+        let ry = matches!(repeat, RepeatMode::RepeatY | RepeatMode::Repeat);
+        Some((axis(x, self.width, rx, mirror)?, axis(y, self.height, ry, mirror)?))
+    }
+
+    fn pixel(&self, x: usize, y: usize) -> ColorA {
+        let offset = (self.width * y + x) * 4;
+        let buf = &self.data[offset..offset + 4];
+        ColorA(buf[0], buf[1], buf[2], buf[3])
+    }
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutlineShape {
+    None,
+    // pixels up to `radius` steps away going only horizontally and vertically. with radius 1 this
+    // is the 4 direct neighbours.
+    Diamond,
+    // pixels up to `radius` away on both axes. with radius 1 this is all 8 neighbours.
+    Square,
+    // pixels within a circle of about `radius`. same as Square for radius 1.
+    Round,
+}
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, Debug)]
+pub struct Outline {
+    pub shape: OutlineShape,
+    pub radius: usize,
+    // moves the outline relative to the text. with radius 0 this gives a drop shadow.
+    pub offset_x: isize,
+    pub offset_y: isize,
+}
+
+#[wasm_bindgen]
+impl Outline {
+    #[wasm_bindgen(constructor)]
+    pub fn new(shape: OutlineShape, radius: usize, offset_x: isize, offset_y: isize) -> Self {
+        Self { shape, radius, offset_x, offset_y }
+    }
+}
+
+impl Outline {
+    fn contains(&self, dx: isize, dy: isize) -> bool {
+        let r = self.radius as isize;
+        match self.shape {
+            OutlineShape::None => false,
+            OutlineShape::Diamond => dx.abs() + dy.abs() <= r,
+            OutlineShape::Square => true,
+            // + r so that the circle is a bit rounder, and doesn't have single pixel nubs
+            OutlineShape::Round => dx * dx + dy * dy <= r * r + r,
+        }
+    }
+}
+
+impl Default for Outline {
+    // the 1px outline all around the text the original generator had.
+    fn default() -> Self { Self::new(OutlineShape::Square, 1, 0, 0) }
+}
+
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct TextItem {
+    pub text: String,
+    pub placement: Placement,
+    pub color: ColorA,
+    pub outline_color: ColorA,
+    pub outline: Outline,
+    // whether to draw this above or below the ellipse. items on the same side of the ellipse are
+    // drawn in order.
+    pub over_ellipse: bool,
+    // if set, the text is repeated across the whole width with this many pixels between copies.
+    pub repeat_gap: Option<usize>,
+    // whether to parse the text as markup (see the markup module).
+    pub markup: bool,
+}
+
+#[wasm_bindgen]
+impl TextItem {
     #[wasm_bindgen(constructor)]
-    pub fn new(width: usize, height: usize, data: Vec<u8>, placement: Placement) -> Self {
-        Self { width, height, data, placement }
+    pub fn new(text: String, placement: Placement, color: ColorA, outline_color: ColorA, over_ellipse: bool) -> Self {
+        Self { text, placement, color, outline_color, outline: Outline::default(), over_ellipse, repeat_gap: None, markup: false }
     }
 }
 
+impl TextItem {
+    pub fn spans(&self) -> Vec<markup::Span> {
+        if self.markup {
+            markup::parse(&self.text)
+        } else {
+            vec![markup::Span { text: self.text.clone(), color: None, outline_color: None }]
+        }
+    }
+
+    pub fn render(&self, font: &dyn font::Font) -> font::TextBitmap {
+        let spans = self.spans();
+        font::render_runs(font, &spans.iter().map(|s| s.text.as_str()).collect::<Vec<_>>())
+    }
+}
+
+// a small image like a flag or a game's logo, drawn along with the text.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
+pub struct Icon {
+    // the icon is placed by its image's placement. icons with Auto horizontal placement are lined
+    // up at the right end of the userbar in order, and text with Auto horizontal placement moves
+    // left to make room for them. Auto vertical placement centers the icon.
+    pub image: BgImage,
+    pub outline: Outline,
+    pub outline_color: ColorA,
+}
+
+#[wasm_bindgen]
+impl Icon {
+    // an icon without an outline.
+    #[wasm_bindgen(constructor)]
+    pub fn new(image: BgImage) -> Self {
+        Self { image, outline: Outline::new(OutlineShape::None, 0, 0, 0), outline_color: ColorA(0, 0, 0, 255) }
+    }
+}
+
+impl Icon {
+    // space between icons, and between them and text.
+    const GAP: isize = 4;
+}
+
 #[wasm_bindgen(getter_with_clone)]
+#[derive(Clone, Debug)]
 pub struct Options {
     pub width: usize,
     pub height: usize,
     pub text: String,
     pub text_placement: Placement,
-    pub bg_top_color: Color,
-    pub bg_bottom_color: Color,
+    pub bg_top_color: ColorA,
+    pub bg_bottom_color: ColorA,
+    // if set, this is drawn instead of the gradient from bg_top_color to bg_bottom_color.
+    pub bg_gradient: Option<Gradient>,
     pub text_color: ColorA,
     pub text_outline_color: ColorA,
-    pub ellipse_color: Option<ColorA>,
-    // the old generator rendered the ellipse over the text, but i feel that can make the text less
+    pub text_outline: Outline,
+    pub gloss: Option<Gloss>,
+    // the old generator rendered the ellipse (gloss) over the text, but i feel that can make the text less
     // legible.
     pub text_over_ellipse: bool,
-    pub border_color: Option<ColorA>,
+    // if set, the text is repeated across the whole width with this many pixels between copies.
+    // used for scrolling marquees.
+    pub text_repeat_gap: Option<usize>,
+    // whether to parse the main text as markup (see the markup module).
+    pub text_markup: bool,
+    // more text to draw besides the main `text`, e.g. a label on the other side.
+    pub extra_text: Vec<TextItem>,
+    pub icons: Vec<Icon>,
+    // None means the built-in Visitor font.
+    #[wasm_bindgen(skip)]
+    pub font: Option<Arc<dyn font::Font>>,
+    pub border: Option<Border>,
+    // the image is cut to a rectangle with corners rounded by this much, leaving the outside
+    // transparent. the border follows the rounded corners.
+    pub corner_radius: f32,
     // these were called "scanlines" in the original generator.
     pub diag_stripes: Option<StripePattern>,
     pub bg_image: Option<BgImage>,
+    // a small image repeated across the whole background, over the background image. its placement
+    // sets where one of the copies goes.
+    pub overlay_tile: Option<BgImage>,
+    // recolors the background image with the background gradient, like its filter's colorize
+    // would, but following the gradient as it's animated.
+    pub bg_image_colorize: bool,
+    // how each effect blends with what's below it.
+    pub diag_stripes_blend: BlendMode,
+    pub bg_image_blend: BlendMode,
+    pub overlay_tile_blend: BlendMode,
+    pub gloss_blend: BlendMode,
+    pub text_blend: BlendMode,
 }
 
 #[wasm_bindgen]
@@ -128,170 +796,172 @@ impl Options {
             text: String::new(),
             width: 350,
             height: 19,
-            bg_top_color: Color(0, 0, 255),
-            bg_bottom_color: Color(128, 255, 255),
+            bg_top_color: ColorA(0, 0, 255, 255),
+            bg_bottom_color: ColorA(128, 255, 255, 255),
+            bg_gradient: None,
             text_color: ColorA(255, 255, 255, 255),
             text_outline_color: ColorA(0, 0, 0, 255),
-            ellipse_color: Some(ColorA(255, 255, 255, 40)),
+            text_outline: Outline::default(),
+            gloss: Some(Gloss::ellipse(ColorA(255, 255, 255, 40))),
             text_over_ellipse: false,
-            border_color: Some(ColorA(0, 0, 0, 255)),
+            text_repeat_gap: None,
+            text_markup: false,
+            extra_text: Vec::new(),
+            icons: Vec::new(),
+            font: None,
+            border: Some(Border::new(ColorA(0, 0, 0, 255))),
+            corner_radius: 0.,
             text_placement: Placement {
                 horz: AxisPlacement { anchor: AxisAnchor::Auto, offset: 0 },
                 vert: AxisPlacement { anchor: AxisAnchor::Auto, offset: 0 },
             },
             diag_stripes: Some(StripePattern {
                 color: ColorA(0, 0, 0, 180),
-                on_main_diagonal: false,
+                kind: PatternKind::Diagonal,
                 spacing: 4,
+                thickness: 1,
+                phase: 0,
             }),
             bg_image: None,
+            overlay_tile: None,
+            diag_stripes_blend: BlendMode::Normal,
+            bg_image_colorize: false,
+            bg_image_blend: BlendMode::Normal,
+            overlay_tile_blend: BlendMode::Normal,
+            gloss_blend: BlendMode::Normal,
+            text_blend: BlendMode::Normal,
         }
     }
 }
 
-// returns flat RGB buffer: array of [r, g, b, r, g, b, ...] with length width*height*3.
-#[wasm_bindgen]
-pub fn generate(opts: &Options) -> Vec<u8> {
-    let width = opts.width;
-    let height = opts.height;
-    let mut canvas = drawing::Canvas::new(opts.width, opts.height);
-    fn to_af_color(c: ColorA) -> drawing::ColorAF {
-        drawing::ColorAF::from_srgb(c.0, c.1, c.2, c.3)
-    }
-    canvas.vert_gradient(
-        0,
-        width - 1,
-        0,
-        height - 1,
-        opts.bg_top_color,
-        opts.bg_bottom_color,
-    );
-    if let Some(StripePattern {
-        color,
-        on_main_diagonal,
-        spacing,
-    }) = opts.diag_stripes
-    {
-        let color = to_af_color(color);
-        for x in 0..width as isize {
-            for y in 0..height as isize {
-                let off = if on_main_diagonal { x - y } else { x + y };
-                if off % (spacing as isize) == 0 {
-                    canvas.draw_px(x as usize, y as usize, color);
-                }
-            }
-        }
+impl Options {
+    pub fn font(&self) -> &dyn font::Font {
+        self.font.as_deref().unwrap_or(font::default_font())
     }
 
-    if let Some(img) = &opts.bg_image {
-        let im_offx = img.placement.horz.to_offset(
-            AxisPlacement { anchor: AxisAnchor::Start, offset: 0 },
-            img.width as isize,
-            width as isize,
-        );
-        let im_offy = img.placement.vert.to_offset(
-            AxisPlacement { anchor: AxisAnchor::Start, offset: 0 },
-            img.height as isize,
-            height as isize,
-        );
-        for x in 0..width as isize {
-            for y in 0..height as isize {
-                let ix = x - im_offx;
-                let iy = y - im_offy;
-                if ix >= 0 && (ix as usize) < img.width && iy >= 0 && (iy as usize) < img.height {
-                    let offset = (img.width * iy as usize + ix as usize) * 4;
-                    let buf = &img.data[offset..offset + 4];
-                    let col = to_af_color(ColorA(buf[0], buf[1], buf[2], buf[3]));
-                    canvas.draw_px(x as usize, y as usize, col);
-                }
+    // the layers that make up the userbar, from the bottom up.
+    pub fn layers(&self) -> Vec<Layer> {
+        let mut layers = Vec::new();
+        let bg_gradient = self.bg_gradient.clone().unwrap_or_else(|| Gradient::vertical(self.bg_top_color, self.bg_bottom_color));
+        layers.push(Layer::gradient(bg_gradient.clone()));
+        layers.extend(self.diag_stripes.map(|x| Layer::pattern(x).with_blend(self.diag_stripes_blend)));
+        layers.extend(self.bg_image.clone().map(|mut x| {
+            if self.bg_image_colorize {
+                x.filter.colorize = Some(bg_gradient);
             }
+            Layer::image(x).with_blend(self.bg_image_blend)
+        }));
+        layers.extend(self.overlay_tile.clone().map(|x| Layer::tile(x).with_blend(self.overlay_tile_blend)));
+        let (icons, reserved) = self.layout_icons();
+        let mut texts: Vec<TextItem> = std::iter::once(self.main_text()).chain(self.extra_text.iter().cloned()).collect();
+        if let Some(offset) = reserved {
+            for t in texts.iter_mut().filter(|t| t.placement.horz.anchor == AxisAnchor::Auto) {
+                t.placement.horz = AxisPlacement { anchor: AxisAnchor::End, offset };
+            }
+        }
+        let text_layer = |t: &TextItem| Layer::text(t.clone()).with_blend(self.text_blend);
+        layers.extend(icons.into_iter().map(Layer::icon));
+        layers.extend(texts.iter().filter(|t| !t.over_ellipse).map(text_layer));
+        layers.extend(self.gloss.map(|x| Layer::gloss(x).with_blend(self.gloss_blend)));
+        layers.extend(texts.iter().filter(|t| t.over_ellipse).map(text_layer));
+        if self.corner_radius > 0. {
+            layers.push(Layer::round_corners(self.corner_radius));
         }
+        layers.extend(self.border.map(|b| Layer::border(b, self.corner_radius)));
+        layers
     }
 
-    let do_ellipse = |canvas: &mut drawing::Canvas| {
-        if let Some(x) = opts.ellipse_color {
-            canvas.ellipse(
-                width as f32 / 2.,
-                0.,
-                width as f32 / 2.,
-                height as f32 / 2.,
-                to_af_color(x),
-            );
-        }
-    };
-
-    if opts.text_over_ellipse {
-        do_ellipse(&mut canvas);
-    }
-
-    let rendered = font::render(&opts.text);
-    let textw = rendered.len() + 2;
-    // +1 because this is the offset of the "main" text, but we computed it with the shadow
-    let text_horz_offset =
-        opts.text_placement
-            .horz
-            .to_offset(AxisPlacement { anchor: AxisAnchor::End, offset: 6 }, textw as isize, width as isize)
-            + 1;
-    // the bounding box is technically 9px tall,
-    // but the height of most letters is only 5px.
-    // so we have +1 for the shadow and -2 for the box height diff
-    let text_vert_offset =
-        opts.text_placement
-            .vert
-            .to_offset(AxisPlacement { anchor: AxisAnchor::Center, offset: 0 }, 7, height as isize)
-            - 1;
-
-    // draw the shadow of the text first
-    let text_outline_color = to_af_color(opts.text_outline_color);
-    for (x, column) in rendered.iter().enumerate() {
-        for y in (0..9).filter(|&y| column[y] == 1) {
-            for (dx, dy) in itertools::iproduct!(-1..=1, -1..=1) {
-                let x = text_horz_offset + x as isize + dx;
-                let y = text_vert_offset + y as isize + dy;
-                if x >= 0 && (x as usize) < width && y >= 0 && (y as usize) < height {
-                    canvas.draw_px(x as usize, y as usize, text_outline_color);
-                }
+    // resolves the Auto placements of icons. also returns how far from the right edge text with
+    // Auto placement should go to stay clear of them, if any were placed automatically.
+    fn layout_icons(&self) -> (Vec<Icon>, Option<isize>) {
+        let mut icons = self.icons.clone();
+        // same as the text's default distance from the edge
+        let mut right = 6;
+        let mut any_auto = false;
+        // going from the right, so that the icons end up in order from left to right
+        for icon in icons.iter_mut().rev() {
+            let place = &mut icon.image.placement;
+            if place.vert.anchor == AxisAnchor::Auto {
+                place.vert = AxisPlacement { anchor: AxisAnchor::Center, offset: 0 };
             }
-        }
-    }
-    // now draw the main text
-    let text_color = to_af_color(opts.text_color);
-    for (x, column) in rendered.iter().enumerate() {
-        for y in (0..9).filter(|&y| column[y] == 1) {
-            let x = text_horz_offset + x as isize;
-            let y = text_vert_offset + y as isize;
-            if x >= 0 && (x as usize) < width && y >= 0 && (y as usize) < height {
-                canvas.draw_px(x as usize, y as usize, text_color);
+            if place.horz.anchor != AxisAnchor::Auto {
+                continue;
             }
+            // the outline sticks out by its radius on both sides, and further to one side by its offset
+            let (r, dx) = match icon.outline.shape {
+                OutlineShape::None => (0, 0),
+                _ => (icon.outline.radius as isize, icon.outline.offset_x),
+            };
+            let (w, _) = icon.image.fitted_size(self.width, self.height);
+            icon.image.placement.horz = AxisPlacement { anchor: AxisAnchor::End, offset: right + r + dx.max(0) };
+            right += w as isize + 2 * r + dx.abs() + Icon::GAP;
+            any_auto = true;
         }
+        (icons, any_auto.then_some(right))
     }
 
-    if !opts.text_over_ellipse {
-        do_ellipse(&mut canvas);
+    // the main text as a TextItem.
+    pub fn main_text(&self) -> TextItem {
+        TextItem {
+            text: self.text.clone(),
+            placement: self.text_placement,
+            color: self.text_color,
+            outline_color: self.text_outline_color,
+            outline: self.text_outline,
+            over_ellipse: self.text_over_ellipse,
+            repeat_gap: self.text_repeat_gap,
+            markup: self.text_markup,
+        }
     }
+}
 
-    if let Some(x) = opts.border_color {
-        let col = to_af_color(x);
-        // don't wanna draw the corners twice, in case the color is transparent
-        canvas.horz_line(1, width - 1, 0, col);
-        canvas.vert_line(1, height - 1, width - 1, col);
-        if width > 1 { canvas.horz_line(0, width - 2, height - 1, col); }
-        if height > 1 { canvas.vert_line(0, height - 2, 0, col); }
+#[wasm_bindgen]
+impl Options {
+    // replaces the font with one loaded from the contents of a BDF file.
+    pub fn load_bdf_font(&mut self, src: &str) -> Result<(), String> {
+        self.font = Some(Arc::new(font::BitmapFont::from_bdf(src).map_err(|e| e.to_string())?));
+        Ok(())
+    }
+    // replaces the font with one loaded from the contents of a BDF, .FON or .FNT file.
+    pub fn load_font(&mut self, data: &[u8]) -> Result<(), String> {
+        self.font = Some(Arc::new(font::BitmapFont::from_bytes(data).map_err(|e| e.to_string())?));
+        Ok(())
+    }
+    // replaces the font with a TrueType/OpenType font, rendered with an em size of `px_size`. if
+    // `threshold` is set, pixels with at least that much coverage (out of 255) are drawn solid and
+    // the rest are left out, instead of antialiasing.
+    pub fn load_truetype_font(&mut self, data: Vec<u8>, px_size: f32, threshold: Option<u8>) -> Result<(), String> {
+        self.font = Some(Arc::new(font::TrueTypeFont::new(data, px_size, threshold).map_err(|e| e.to_string())?));
+        Ok(())
     }
-    canvas.get_buf()
 }
 
-// im so lazy lol
+// escapes text so it shows up as is when markup is enabled.
 #[wasm_bindgen]
-pub fn generate_rgba(o: &Options) -> Vec<u8> {
-    let v = generate(o);
-    let pixs = v.len() / 3;
-    let mut out = vec![0; pixs * 4];
-    for i in 0..pixs {
-        out[i*4 + 0] = v[i*3 + 0];
-        out[i*4 + 1] = v[i*3 + 1];
-        out[i*4 + 2] = v[i*3 + 2];
-        out[i*4 + 3] = 255;
-    }
-    out
+pub fn escape_markup(text: &str) -> String {
+    markup::escape(text)
+}
+
+impl Default for Options {
+    fn default() -> Self { Self::new() }
+}
+
+// returns flat RGBA buffer (not premultiplied): array of [r, g, b, a, r, g, b, a, ...] with length
+// width*height*4.
+#[wasm_bindgen]
+pub fn generate(opts: &Options) -> Vec<u8> {
+    layer::render(opts.width, opts.height, opts.font(), &opts.layers())
+}
+
+// like `generate`, but draws the given layers instead of the ones made from `opts`. only the size
+// and font are taken from `opts`.
+#[wasm_bindgen]
+pub fn generate_layers(opts: &Options, layers: Vec<layer::Layer>) -> Vec<u8> {
+    layer::render(opts.width, opts.height, opts.font(), &layers)
+}
+
+// kept for existing callers, now that generate returns RGBA as well.
+#[wasm_bindgen]
+pub fn generate_rgba(opts: &Options) -> Vec<u8> {
+    generate(opts)
 }
@@ -0,0 +1,177 @@
+// BBCode-like markup for styling parts of a text, e.g. "I love [color=#f80]Rust[/color]".
+//
+// supported tags are [color=HEX]...[/color] and [outline=HEX]...[/outline], which can be nested.
+// "[[" is a literal "[". anything that isn't a valid tag (including closing tags that weren't
+// opened) is left in the text as is.
+use crate::ColorA;
+
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub text: String,
+    // None means to use the text item's color
+    pub color: Option<ColorA>,
+    pub outline_color: Option<ColorA>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum TagKind {
+    Color,
+    Outline,
+}
+
+enum Tag {
+    Open(TagKind, ColorA),
+    Close(TagKind),
+}
+
+// parses a tag at the start of `s`, returning it and its length.
+fn parse_tag(s: &str) -> Option<(Tag, usize)> {
+    let end = s.find(']')?;
+    let inner = &s[1..end];
+    let kind = |name: &str| match name {
+        "color" => Some(TagKind::Color),
+        "outline" => Some(TagKind::Outline),
+        _ => None,
+    };
+    let tag = if let Some(name) = inner.strip_prefix('/') {
+        Tag::Close(kind(name)?)
+    } else {
+        let (name, value) = inner.split_once('=')?;
+        Tag::Open(kind(name)?, ColorA::from_hex(value).ok()?)
+    };
+    Some((tag, end + 1))
+}
+
+pub fn parse(src: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut open: Vec<(TagKind, ColorA)> = Vec::new();
+    let mut cur = String::new();
+    let flush = |cur: &mut String, spans: &mut Vec<Span>, open: &[(TagKind, ColorA)]| {
+        if cur.is_empty() {
+            return;
+        }
+        let innermost = |kind| open.iter().rev().find(|x| x.0 == kind).map(|x| x.1);
+        spans.push(Span {
+            text: std::mem::take(cur),
+            color: innermost(TagKind::Color),
+            outline_color: innermost(TagKind::Outline),
+        });
+    };
+    let mut rest = src;
+    while let Some(i) = rest.find('[') {
+        cur.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix("[[") {
+            cur.push('[');
+            rest = r;
+            continue;
+        }
+        let tag = parse_tag(rest).filter(|(tag, _)| match tag {
+            Tag::Open(..) => true,
+            Tag::Close(kind) => open.iter().any(|x| x.0 == *kind),
+        });
+        let Some((tag, len)) = tag else {
+            cur.push('[');
+            rest = &rest[1..];
+            continue;
+        };
+        flush(&mut cur, &mut spans, &open);
+        match tag {
+            Tag::Open(kind, color) => open.push((kind, color)),
+            Tag::Close(kind) => {
+                let i = open.iter().rposition(|x| x.0 == kind).unwrap();
+                open.remove(i);
+            }
+        }
+        rest = &rest[len..];
+    }
+    cur.push_str(rest);
+    flush(&mut cur, &mut spans, &open);
+    spans
+}
+
+// escapes `text` so that parsing it as markup gives back the same text.
+pub fn escape(text: &str) -> String {
+    text.replace('[', "[[")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (text, color, outline color) of each span, with colors as RGBA tuples
+    type Rgba = (u8, u8, u8, u8);
+    fn spans(src: &str) -> Vec<(String, Option<Rgba>, Option<Rgba>)> {
+        let rgba = |c: Option<ColorA>| c.map(|c| (c.0, c.1, c.2, c.3));
+        parse(src).into_iter().map(|s| (s.text, rgba(s.color), rgba(s.outline_color))).collect()
+    }
+
+    const RED: Option<Rgba> = Some((255, 0, 0, 255));
+    const BLUE: Option<Rgba> = Some((0, 0, 255, 255));
+
+    #[test]
+    fn plain_text() {
+        assert_eq!(spans("hello"), [("hello".into(), None, None)]);
+        assert!(parse("").is_empty());
+    }
+
+    #[test]
+    fn color_and_outline() {
+        assert_eq!(
+            spans("a[color=#f00]b[/color]c[outline=00f]d[/outline]"),
+            [
+                ("a".into(), None, None),
+                ("b".into(), RED, None),
+                ("c".into(), None, None),
+                ("d".into(), None, BLUE),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_tags() {
+        assert_eq!(
+            spans("[color=#f00]a[outline=#00f]b[color=#00f]c[/color]d[/outline][/color]e"),
+            [
+                ("a".into(), RED, None),
+                ("b".into(), RED, BLUE),
+                ("c".into(), BLUE, BLUE),
+                ("d".into(), RED, BLUE),
+                ("e".into(), None, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn double_bracket_is_literal() {
+        assert_eq!(spans("a[[color=#f00]b"), [("a[color=#f00]b".into(), None, None)]);
+        assert_eq!(spans("[[[color=#f00]x"), [("[".into(), None, None), ("x".into(), RED, None)]);
+    }
+
+    #[test]
+    fn unclosed_tag_lasts_until_the_end() {
+        assert_eq!(spans("a[color=#f00]b"), [("a".into(), None, None), ("b".into(), RED, None)]);
+    }
+
+    #[test]
+    fn unmatched_closing_tag_is_literal() {
+        assert_eq!(spans("a[/color]b"), [("a[/color]b".into(), None, None)]);
+        assert_eq!(spans("[outline=#00f]a[/color]b"), [("a[/color]b".into(), None, BLUE)]);
+    }
+
+    #[test]
+    fn invalid_tags_are_literal() {
+        for src in ["[color=#zzz]a", "[color=#12345]a", "[color]a", "[bold=#fff]a", "[color=#fff a", "a["] {
+            assert_eq!(spans(src), [(src.into(), None, None)], "{src:?}");
+        }
+    }
+
+    #[test]
+    fn escape_round_trip() {
+        for s in ["", "plain", "[", "[[", "a[color=#f00]b[/color]", "[/outline]]", "x[[y[", "ünïcode [ok]"] {
+            let text: String = parse(&escape(s)).into_iter().map(|span| span.text).collect();
+            assert_eq!(text, s);
+            assert!(parse(&escape(s)).iter().all(|span| span.color.is_none() && span.outline_color.is_none()));
+        }
+    }
+}
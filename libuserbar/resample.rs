@@ -0,0 +1,92 @@
+use crate::drawing::ColorAF;
+use crate::Resample;
+
+// scales an RGBA image to new_w x new_h. filtering is done in linear light with premultiplied
+// alpha, so that dark or transparent pixels don't bleed into their neighbours.
+pub(crate) fn resize(data: &[u8], w: usize, h: usize, new_w: usize, new_h: usize, filter: Resample) -> Vec<u8> {
+    if w == 0 || h == 0 || new_w == 0 || new_h == 0 {
+        return vec![0; new_w * new_h * 4];
+    }
+    let src: Vec<[f32; 4]> = data
+        .chunks_exact(4)
+        .map(|px| ColorAF::from_srgb(px[0], px[1], px[2], px[3]).premultiplied())
+        .collect();
+    // horizontal pass, then vertical
+    let xw = weights(w, new_w, filter);
+    let mut tmp = vec![[0.; 4]; new_w * h];
+    for (y, x) in itertools::iproduct!(0..h, 0..new_w) {
+        tmp[y * new_w + x] = apply(&xw[x], |i| src[y * w + i]);
+    }
+    let yw = weights(h, new_h, filter);
+    let mut out = Vec::with_capacity(new_w * new_h * 4);
+    for (y, x) in itertools::iproduct!(0..new_h, 0..new_w) {
+        let [r, g, b, a] = apply(&yw[y], |i| tmp[i * new_w + x]);
+        // lanczos can overshoot
+        let a = a.clamp(0., 1.);
+        let unmul = |c: f32| if a > 0. { (c / a).clamp(0., 1.) } else { 0. };
+        out.extend_from_slice(&[to_srgb(unmul(r)), to_srgb(unmul(g)), to_srgb(unmul(b)), (a * 255.).round() as u8]);
+    }
+    out
+}
+
+fn apply(weights: &[(usize, f32)], px: impl Fn(usize) -> [f32; 4]) -> [f32; 4] {
+    let mut acc = [0.; 4];
+    for &(i, wt) in weights {
+        for (a, c) in acc.iter_mut().zip(px(i)) {
+            *a += c * wt;
+        }
+    }
+    acc
+}
+
+// for each output pixel along an axis, the source pixels it's made of and how much each one counts.
+fn weights(len: usize, new_len: usize, filter: Resample) -> Vec<Vec<(usize, f32)>> {
+    let scale = len as f32 / new_len as f32;
+    let (kernel, support): (fn(f32) -> f32, f32) = match filter {
+        Resample::Nearest => {
+            return (0..new_len)
+                .map(|i| vec![((((i as f32 + 0.5) * scale) as usize).min(len - 1), 1.)])
+                .collect();
+        }
+        Resample::Bilinear => (|x| (1. - x.abs()).max(0.), 1.),
+        Resample::Lanczos => (lanczos3, 3.),
+    };
+    // when shrinking, the kernel is stretched so that every source pixel gets used
+    let stretch = scale.max(1.);
+    (0..new_len)
+        .map(|i| {
+            let center = (i as f32 + 0.5) * scale - 0.5;
+            let lo = (center - support * stretch).ceil() as isize;
+            let hi = (center + support * stretch).floor() as isize;
+            let mut ws: Vec<(usize, f32)> = (lo..=hi)
+                .map(|j| (j.clamp(0, len as isize - 1) as usize, kernel((j as f32 - center) / stretch)))
+                .collect();
+            let total: f32 = ws.iter().map(|w| w.1).sum();
+            if total != 0. {
+                ws.iter_mut().for_each(|w| w.1 /= total);
+            }
+            ws
+        })
+        .collect()
+}
+
+fn lanczos3(x: f32) -> f32 {
+    fn sinc(x: f32) -> f32 {
+        if x == 0. {
+            1.
+        } else {
+            let x = x * std::f32::consts::PI;
+            x.sin() / x
+        }
+    }
+    if x.abs() < 3. {
+        sinc(x) * sinc(x / 3.)
+    } else {
+        0.
+    }
+}
+
+fn to_srgb(x: f32) -> u8 {
+    let c = if x <= 0.0031308 { x * 12.92 } else { 1.055 * x.powf(1. / 2.4) - 0.055 };
+    (c * 255.).round() as u8
+}
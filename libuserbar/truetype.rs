@@ -0,0 +1,91 @@
+// TrueType/OpenType fonts, rasterized with ab_glyph. there's no hinting, but glyphs are placed on
+// whole pixels, so pixel fonts drawn at (a multiple of) their design size come out crisp.
+use crate::font::{Font, Glyph, ParseError};
+use ab_glyph::{Font as _, FontArc, PxScale, ScaleFont};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+pub struct TrueTypeFont {
+    font: FontArc,
+    scale: PxScale,
+    // if set, pixels are either fully on (coverage >= threshold) or off, instead of antialiased.
+    threshold: Option<u8>,
+    cap_height: usize,
+    // rasterizing is slow and text gets redrawn on every frame, so glyphs are kept once drawn
+    cache: Mutex<HashMap<char, Glyph>>,
+}
+
+impl std::fmt::Debug for TrueTypeFont {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TrueTypeFont").field("scale", &self.scale).field("threshold", &self.threshold).finish()
+    }
+}
+
+impl TrueTypeFont {
+    // returns whether `data` looks like a TrueType/OpenType font or collection.
+    pub fn detect(data: &[u8]) -> bool {
+        [b"\0\x01\0\0", b"OTTO", b"true", b"ttcf"].iter().any(|m| data.starts_with(*m))
+    }
+
+    // `px_size` is the size of the em square in pixels.
+    pub fn new(data: Vec<u8>, px_size: f32, threshold: Option<u8>) -> Result<TrueTypeFont, ParseError> {
+        let font = FontArc::try_from_vec(data).or(Err(ParseError("couldn't parse TrueType font".into())))?;
+        let units_per_em = font.units_per_em().ok_or(ParseError("font has no units per em".into()))?;
+        let scale = PxScale::from(px_size * font.height_unscaled() / units_per_em);
+        let mut this = TrueTypeFont { font, scale, threshold, cap_height: 0, cache: Mutex::default() };
+        // fonts have a cap height in the OS/2 table, but it's often inaccurate, so measure X instead
+        this.cap_height = this.glyph('X').map_or(this.ascent(), |g| g.top.max(0) as usize);
+        Ok(this)
+    }
+}
+
+impl TrueTypeFont {
+    fn rasterize(&self, c: char) -> Glyph {
+        let font = self.font.as_scaled(self.scale);
+        let id = font.glyph_id(c);
+        let advance = font.h_advance(id).round().max(0.) as usize;
+        let Some(outlined) = font.outline_glyph(id.with_scale(self.scale)) else {
+            // no outline, e.g. a space
+            return Glyph { advance, width: 0, height: 0, left: 0, top: 0, data: Vec::new() };
+        };
+        let bounds = outlined.px_bounds();
+        let width = bounds.width() as usize;
+        let height = bounds.height() as usize;
+        let mut data = vec![0; width * height];
+        outlined.draw(|x, y, cov| {
+            let cov = (cov.clamp(0., 1.) * 255.).round() as u8;
+            data[y as usize * width + x as usize] = match self.threshold {
+                // a threshold of 0 would light up the whole bounding box
+                Some(t) if cov >= t.max(1) => 255,
+                Some(_) => 0,
+                None => cov,
+            };
+        });
+        Glyph {
+            advance,
+            width,
+            height,
+            left: bounds.min.x as isize,
+            // bounds are y-down from the baseline
+            top: -bounds.min.y as isize,
+            data,
+        }
+    }
+}
+
+impl Font for TrueTypeFont {
+    fn glyph(&self, c: char) -> Option<Cow<'_, Glyph>> {
+        let mut cache = self.cache.lock().unwrap();
+        Some(Cow::Owned(cache.entry(c).or_insert_with(|| self.rasterize(c)).clone()))
+    }
+    fn ascent(&self) -> usize {
+        self.font.as_scaled(self.scale).ascent().round().max(0.) as usize
+    }
+    fn descent(&self) -> usize {
+        (-self.font.as_scaled(self.scale).descent()).round().max(0.) as usize
+    }
+    fn cap_height(&self) -> usize {
+        self.cap_height
+    }
+}
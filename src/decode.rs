@@ -0,0 +1,451 @@
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    // RGBA buffers with 8 bits per channel and sRGB colors, each with how long it's shown in
+    // milliseconds. still images have a single frame.
+    pub frames: Vec<(Vec<u8>, u32)>,
+}
+
+impl Image {
+    fn still(width: usize, height: usize, data: Vec<u8>) -> Image {
+        Image { width, height, frames: vec![(data, 0)] }
+    }
+
+    // the frame showing `time` milliseconds into the animation, looping around.
+    pub fn frame_at(&self, time: u32) -> &[u8] {
+        let total: u32 = self.frames.iter().map(|f| f.1).sum();
+        let mut t = if total == 0 { 0 } else { time % total };
+        for (data, delay) in &self.frames {
+            if t < *delay {
+                return data;
+            }
+            t -= delay;
+        }
+        &self.frames[0].0
+    }
+}
+
+// reads a PNG, GIF, BMP, JPEG or QOI file, going by its contents rather than its name.
+pub fn read(name: &str) -> anyhow::Result<Image> {
+    let data = std::fs::read(name)?;
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png(&data)
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        gif(&data)
+    } else if data.starts_with(b"BM") {
+        bmp(&data)
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        jpeg(&data)
+    } else if data.starts_with(b"qoif") {
+        qoi(&data)
+    } else {
+        anyhow::bail!("{name}: unknown image format, expected PNG, GIF, BMP, JPEG or QOI")
+    }
+}
+
+// reads a PNG of any color type and bit depth, converting other gammas to sRGB.
+fn png(data: &[u8]) -> anyhow::Result<Image> {
+    let mut decoder = png::Decoder::new(data);
+    // turns palettes into RGB, tRNS chunks into an alpha channel and 1/2/4-bit gray into 8-bit
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let h = reader.next_frame(&mut buf)?;
+    buf.truncate(h.buffer_size());
+    let info = reader.info();
+    // the gamma the file was encoded with, if it isn't sRGB. 1/2.2 is close enough to sRGB that
+    // it's treated as such, like browsers do.
+    let gamma = match (info.srgb, info.source_gamma) {
+        (None, Some(g)) if (g.into_value() - 1. / 2.2).abs() > 0.01 => Some(g.into_value()),
+        _ => None,
+    };
+    let samples: Vec<f32> = match h.bit_depth {
+        png::BitDepth::Sixteen => buf.chunks_exact(2).map(|x| u16::from_be_bytes([x[0], x[1]]) as f32 / 65535.).collect(),
+        _ => buf.iter().map(|&x| x as f32 / 255.).collect(),
+    };
+    let to_u8 = |x: f32| (x * 255.).round() as u8;
+    let color = |x: f32| match gamma {
+        Some(g) => {
+            let linear = x.powf(1. / g);
+            to_u8(if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1. / 2.4) - 0.055 })
+        }
+        None => to_u8(x),
+    };
+    let rgba = |px: &[f32]| -> [u8; 4] {
+        match h.color_type {
+            png::ColorType::Grayscale => [color(px[0]), color(px[0]), color(px[0]), 255],
+            png::ColorType::GrayscaleAlpha => [color(px[0]), color(px[0]), color(px[0]), to_u8(px[1])],
+            png::ColorType::Rgb => [color(px[0]), color(px[1]), color(px[2]), 255],
+            _ => [color(px[0]), color(px[1]), color(px[2]), to_u8(px[3])],
+        }
+    };
+    let channels = h.color_type.samples();
+    anyhow::ensure!(channels > 0 && h.color_type != png::ColorType::Indexed, "couldn't expand the image's palette");
+    let data = samples.chunks_exact(channels).flat_map(rgba).collect();
+    Ok(Image::still(h.width as usize, h.height as usize, data))
+}
+
+// reads all frames of a GIF, each one drawn over what the previous ones left behind.
+fn gif(data: &[u8]) -> anyhow::Result<Image> {
+    let mut opts = gif::DecodeOptions::new();
+    opts.set_color_output(gif::ColorOutput::RGBA);
+    let mut decoder = opts.read_info(data)?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    let mut screen = vec![0; width * height * 4];
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame()? {
+        let previous = (frame.dispose == gif::DisposalMethod::Previous).then(|| screen.clone());
+        let (left, top) = (frame.left as usize, frame.top as usize);
+        // parts of the frame outside the screen are cut off
+        let rect = move || {
+            let w = frame.width as usize;
+            (0..frame.height as usize).flat_map(move |y| (0..w).map(move |x| (x, y, y * w + x)))
+        };
+        let on_screen = |x: usize, y: usize| left + x < width && top + y < height;
+        for (x, y, i) in rect() {
+            let px = &frame.buffer[i * 4..i * 4 + 4];
+            if px[3] != 0 && on_screen(x, y) {
+                let o = ((top + y) * width + left + x) * 4;
+                screen[o..o + 4].copy_from_slice(px);
+            }
+        }
+        // browsers show frames without a delay for 100ms
+        let delay = if frame.delay <= 1 { 100 } else { frame.delay as u32 * 10 };
+        frames.push((screen.clone(), delay));
+        match frame.dispose {
+            gif::DisposalMethod::Background => {
+                for (x, y, _) in rect().filter(|&(x, y, _)| on_screen(x, y)) {
+                    let o = ((top + y) * width + left + x) * 4;
+                    screen[o..o + 4].fill(0);
+                }
+            }
+            gif::DisposalMethod::Previous => screen = previous.unwrap(),
+            _ => (),
+        }
+    }
+    anyhow::ensure!(!frames.is_empty(), "GIF has no frames");
+    Ok(Image { width, height, frames })
+}
+
+fn jpeg(data: &[u8]) -> anyhow::Result<Image> {
+    use jpeg_decoder::PixelFormat;
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let pixels = decoder.decode()?;
+    let info = decoder.info().unwrap();
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = match info.pixel_format {
+        PixelFormat::L8 | PixelFormat::L16 => 1,
+        PixelFormat::RGB24 => 3,
+        PixelFormat::CMYK32 => 4,
+    };
+    // lossless JPEGs that aren't 8-bit come out as 16-bit samples, whatever the pixel format says
+    let wide = pixels.len() == width * height * channels * 2;
+    let rgba: Vec<u8> = match info.pixel_format {
+        PixelFormat::L8 | PixelFormat::L16 if wide => {
+            // samples go up to 2^precision - 1, and the decoder doesn't say what the precision is
+            let precision = jpeg_precision(data).ok_or_else(|| anyhow::anyhow!("JPEG has no frame header"))?;
+            let max = ((1u32 << precision) - 1) as f32;
+            pixels
+                .chunks_exact(2)
+                .flat_map(|x| {
+                    let l = (u16::from_ne_bytes([x[0], x[1]]) as f32 * 255. / max).round().min(255.) as u8;
+                    [l, l, l, 255]
+                })
+                .collect()
+        }
+        _ if wide => anyhow::bail!("color JPEGs with more than 8 bits per sample aren't supported"),
+        PixelFormat::L8 | PixelFormat::L16 => pixels.iter().flat_map(|&l| [l, l, l, 255]).collect(),
+        PixelFormat::RGB24 => pixels.chunks_exact(3).flat_map(|x| [x[0], x[1], x[2], 255]).collect(),
+        // the decoder gives inverted CMYK, so that multiplying by K gives RGB
+        PixelFormat::CMYK32 => pixels
+            .chunks_exact(4)
+            .flat_map(|x| {
+                let mul = |c: u8| (c as u32 * x[3] as u32 / 255) as u8;
+                [mul(x[0]), mul(x[1]), mul(x[2]), 255]
+            })
+            .collect(),
+    };
+    anyhow::ensure!(rgba.len() == width * height * 4, "JPEG decoded to the wrong number of pixels");
+    Ok(Image::still(width, height, rgba))
+}
+
+// the sample precision in bits, from the first frame header.
+fn jpeg_precision(data: &[u8]) -> Option<u8> {
+    let mut i = 2;
+    while let [0xff, marker, hi, lo, ..] = *data.get(i..)? {
+        // SOF0 to SOF15, except DHT, JPG and DAC which share the range
+        if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+            return data.get(i + 4).copied();
+        }
+        i += 2 + u16::from_be_bytes([hi, lo]) as usize;
+    }
+    None
+}
+
+fn qoi(data: &[u8]) -> anyhow::Result<Image> {
+    let (header, pixels) = qoi::decode_to_vec(data)?;
+    let rgba = match header.channels {
+        qoi::Channels::Rgb => pixels.chunks_exact(3).flat_map(|x| [x[0], x[1], x[2], 255]).collect(),
+        qoi::Channels::Rgba => pixels,
+    };
+    Ok(Image::still(header.width as usize, header.height as usize, rgba))
+}
+
+// 8192x8192, which is more than anything reasonable to put in a userbar.
+const MAX_RLE_PIXELS: usize = 1 << 26;
+
+// reads an uncompressed, bitfield or RLE-compressed BMP.
+fn bmp(data: &[u8]) -> anyhow::Result<Image> {
+    let bad = || anyhow::anyhow!("BMP file is truncated or corrupt");
+    let u16_at = |i: usize| data.get(i..i + 2).map(|x| u16::from_le_bytes([x[0], x[1]])).ok_or_else(bad);
+    let u32_at = |i: usize| data.get(i..i + 4).map(|x| u32::from_le_bytes([x[0], x[1], x[2], x[3]])).ok_or_else(bad);
+    let pixels_at = u32_at(10)? as usize;
+    let header_size = u32_at(14)? as usize;
+    // OS/2 bitmaps have a smaller header and 3-byte palette entries
+    let core = header_size == 12;
+    let (width, height, bpp, compression, palette_size) = if core {
+        (u16_at(18)? as i32, u16_at(20)? as i16 as i32, u16_at(24)?, 0, 0)
+    } else {
+        (u32_at(18)? as i32, u32_at(22)? as i32, u16_at(28)?, u32_at(30)?, u32_at(46)? as usize)
+    };
+    anyhow::ensure!(width >= 0, "BMP has a negative width");
+    let (width, top_down, height) = (width as usize, height < 0, height.unsigned_abs() as usize);
+    // where to find the masks of each channel, as (r, g, b, a)
+    let mut masks = match bpp {
+        16 => (0x7c00, 0x3e0, 0x1f, 0),
+        _ => (0xff0000, 0xff00, 0xff, 0),
+    };
+    let mut palette_at = 14 + header_size;
+    if compression == 3 || compression == 6 {
+        let at = if header_size >= 52 { 54 } else { 14 + header_size };
+        masks = (u32_at(at)?, u32_at(at + 4)?, u32_at(at + 8)?, 0);
+        if header_size >= 56 {
+            masks.3 = u32_at(66)?;
+        } else if compression == 6 {
+            masks.3 = u32_at(at + 12)?;
+        }
+        if header_size < 52 {
+            palette_at += if compression == 6 { 16 } else { 12 };
+        }
+    } else if compression != 0 && !(compression == 1 && bpp == 8) && !(compression == 2 && bpp == 4) {
+        anyhow::bail!("unsupported BMP compression method {compression}");
+    }
+
+    let entry_size = if core { 3 } else { 4 };
+    let palette_len = if palette_size == 0 && bpp <= 8 { 1 << bpp } else { palette_size };
+    let palette: Vec<[u8; 4]> = (0..palette_len)
+        .map_while(|i| data.get(palette_at + i * entry_size..palette_at + i * entry_size + 3))
+        .map(|x| [x[2], x[1], x[0], 255])
+        .collect();
+    let color = |i: u8| palette.get(i as usize).copied().ok_or_else(|| anyhow::anyhow!("BMP palette index out of range"));
+
+    let pixels = data.get(pixels_at..).ok_or_else(bad)?;
+    let too_large = || anyhow::anyhow!("BMP is too large");
+    let size = width.checked_mul(height).filter(|&n| n <= isize::MAX as usize / 4).ok_or_else(too_large)?;
+    anyhow::ensure!(size > 0, "BMP has no pixels");
+    let compressed = compression == 1 || compression == 2;
+    // a few bytes of RLE can cover any number of pixels, so those have to be limited some other way
+    anyhow::ensure!(!compressed || size <= MAX_RLE_PIXELS, too_large());
+    let stride = width.checked_mul(bpp as usize).ok_or_else(too_large)?.div_ceil(32) * 4;
+    anyhow::ensure!(compressed || stride.checked_mul(height).is_some_and(|n| n <= pixels.len()), bad());
+    let mut rgba = vec![0; size * 4];
+    // rows are stored bottom to top, unless the height is negative
+    let mut put = |x: usize, y: usize, px: [u8; 4]| {
+        let y = if top_down { y } else { height - 1 - y };
+        rgba[(y * width + x) * 4..][..4].copy_from_slice(&px);
+    };
+    if compressed {
+        for (x, y, i) in rle(pixels, width, height, compression == 2)? {
+            put(x, y, color(i)?);
+        }
+        return Ok(Image::still(width, height, rgba));
+    }
+    let channel = |v: u32, mask: u32| {
+        if mask == 0 {
+            return 255;
+        }
+        let max = mask >> mask.trailing_zeros();
+        (((v & mask) >> mask.trailing_zeros()) as f32 * 255. / max as f32).round() as u8
+    };
+    for y in 0..height {
+        let row = pixels.get(y * stride..y * stride + stride).ok_or_else(bad)?;
+        for x in 0..width {
+            let px = match bpp {
+                1 | 2 | 4 | 8 => {
+                    let bit = x * bpp as usize;
+                    let shift = 8 - bpp as usize - bit % 8;
+                    color((row[bit / 8] >> shift) & ((1 << bpp) - 1) as u8)?
+                }
+                16 | 32 => {
+                    let v = match bpp {
+                        16 => u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32,
+                        _ => u32::from_le_bytes([row[x * 4], row[x * 4 + 1], row[x * 4 + 2], row[x * 4 + 3]]),
+                    };
+                    [channel(v, masks.0), channel(v, masks.1), channel(v, masks.2), channel(v, masks.3)]
+                }
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                _ => anyhow::bail!("unsupported BMP bit depth {bpp}"),
+            };
+            put(x, y, px);
+        }
+    }
+    Ok(Image::still(width, height, rgba))
+}
+
+// decodes RLE8 or RLE4 data into (x, y, palette index) for each pixel that's set. skipped pixels
+// are left transparent.
+fn rle(data: &[u8], width: usize, height: usize, four_bit: bool) -> anyhow::Result<Vec<(usize, usize, u8)>> {
+    let mut out = Vec::new();
+    let (mut x, mut y, mut i) = (0, 0, 0);
+    let mut next = || {
+        i += 1;
+        data.get(i - 1).copied().ok_or_else(|| anyhow::anyhow!("BMP file is truncated or corrupt"))
+    };
+    let nibble = |byte: u8, n: usize| if !four_bit { byte } else if n.is_multiple_of(2) { byte >> 4 } else { byte & 15 };
+    while y < height {
+        let (count, value) = (next()? as usize, next()?);
+        if count > 0 {
+            for n in 0..count {
+                if x < width {
+                    out.push((x, y, nibble(value, n)));
+                }
+                x += 1;
+            }
+            continue;
+        }
+        match value {
+            0 => (x, y) = (0, y + 1),
+            1 => break,
+            2 => (x, y) = (x + next()? as usize, y + next()? as usize),
+            n => {
+                let n = n as usize;
+                let bytes: Vec<u8> = (0..if four_bit { n.div_ceil(2) } else { n }).map(|_| next()).collect::<Result<_, _>>()?;
+                for k in 0..n {
+                    let byte = if four_bit { bytes[k / 2] } else { bytes[k] };
+                    if x < width {
+                        out.push((x, y, nibble(byte, k)));
+                    }
+                    x += 1;
+                }
+                // runs of literal pixels are padded to 2 bytes
+                if bytes.len() % 2 == 1 {
+                    next()?;
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a BMP with a 40-byte info header. `extra` is the palette or the bitfield masks.
+    fn file(width: i32, height: i32, bpp: u16, compression: u32, extra: &[u8], pixels: &[u8]) -> Vec<u8> {
+        let pixels_at = 14 + 40 + extra.len() as u32;
+        let palette_size = if bpp <= 8 { extra.len() as u32 / 4 } else { 0 };
+        let mut f = b"BM".to_vec();
+        f.extend((pixels_at + pixels.len() as u32).to_le_bytes());
+        f.extend([0; 4]);
+        f.extend(pixels_at.to_le_bytes());
+        f.extend(40u32.to_le_bytes());
+        f.extend(width.to_le_bytes());
+        f.extend(height.to_le_bytes());
+        f.extend(1u16.to_le_bytes());
+        f.extend(bpp.to_le_bytes());
+        f.extend(compression.to_le_bytes());
+        f.extend([0; 12]);
+        f.extend(palette_size.to_le_bytes());
+        f.extend([0; 4]);
+        f.extend(extra);
+        f.extend(pixels);
+        f
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+    const NONE: [u8; 4] = [0; 4];
+    // red, blue, in BMP's BGR0 order
+    const PALETTE: [u8; 8] = [0, 0, 255, 0, 255, 0, 0, 0];
+
+    fn pixels(data: &[u8]) -> Vec<[u8; 4]> {
+        let img = bmp(data).unwrap();
+        img.frames[0].0.chunks_exact(4).map(|x| x.try_into().unwrap()).collect()
+    }
+
+    #[test]
+    fn bmp_1bpp() {
+        // rows are bottom to top and padded to 4 bytes
+        let data = file(3, 2, 1, 0, &PALETTE, &[0b1010_0000, 0, 0, 0, 0b0100_0000, 0, 0, 0]);
+        assert_eq!(pixels(&data), [RED, BLUE, RED, BLUE, RED, BLUE]);
+    }
+
+    #[test]
+    fn bmp_4bpp_and_8bpp() {
+        assert_eq!(pixels(&file(3, 1, 4, 0, &PALETTE, &[0x01, 0x10, 0, 0])), [RED, BLUE, BLUE]);
+        assert_eq!(pixels(&file(2, 1, 8, 0, &PALETTE, &[1, 0, 0, 0])), [BLUE, RED]);
+        // indices past the end of the palette are an error
+        assert!(bmp(&file(1, 1, 8, 0, &PALETTE, &[2, 0, 0, 0])).is_err());
+    }
+
+    #[test]
+    fn bmp_24bpp_and_32bpp() {
+        let data = file(1, 2, 24, 0, &[], &[255, 0, 0, 0, 0, 0, 255, 0]);
+        assert_eq!(pixels(&data), [RED, BLUE]);
+        // without bitfields, 32-bit pixels are BGR with the alpha byte ignored
+        assert_eq!(pixels(&file(1, 1, 32, 0, &[], &[1, 2, 3, 4])), [[3, 2, 1, 255]]);
+    }
+
+    #[test]
+    fn bmp_bitfields() {
+        let masks: Vec<u8> = [0xf800u32, 0x7e0, 0x1f].iter().flat_map(|m| m.to_le_bytes()).collect();
+        assert_eq!(pixels(&file(2, 1, 16, 3, &masks, &[0x00, 0xf8, 0x1f, 0x00])), [RED, BLUE]);
+        let masks: Vec<u8> = [0xffu32, 0xff00, 0xff0000, 0xff000000].iter().flat_map(|m| m.to_le_bytes()).collect();
+        assert_eq!(pixels(&file(1, 1, 32, 6, &masks, &[1, 2, 3, 4])), [[1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn bmp_top_down() {
+        let data = file(1, -2, 24, 0, &[], &[255, 0, 0, 0, 0, 0, 255, 0]);
+        assert_eq!(pixels(&data), [BLUE, RED]);
+    }
+
+    #[test]
+    fn bmp_rle8() {
+        // bottom row: a run of 3 blue pixels, then end of line. top row: 3 literal pixels (padded
+        // to an even length), then end of bitmap. the rest is left transparent.
+        let data = file(4, 2, 8, 1, &PALETTE, &[3, 1, 0, 0, 0, 3, 0, 1, 0, 0, 0, 1]);
+        assert_eq!(pixels(&data), [RED, BLUE, RED, NONE, BLUE, BLUE, BLUE, NONE]);
+    }
+
+    #[test]
+    fn bmp_rle4() {
+        // a run alternates between the two nibbles, then a delta skips one pixel right
+        let data = file(5, 1, 4, 2, &PALETTE, &[3, 0x10, 0, 2, 1, 0, 1, 0x10, 0, 1]);
+        assert_eq!(pixels(&data), [BLUE, RED, BLUE, NONE, BLUE]);
+    }
+
+    #[test]
+    fn bmp_truncated() {
+        let data = file(2, 2, 24, 0, &[], &[0; 16]);
+        assert!(bmp(&data[..data.len() - 1]).is_err());
+        let data = file(4, 1, 8, 1, &PALETTE, &[3, 1, 0]);
+        assert!(bmp(&data).is_err());
+        assert!(bmp(&data[..30]).is_err());
+    }
+
+    #[test]
+    fn bmp_too_large() {
+        assert!(bmp(&file(0x7fffffff, -0x7fffffff, 24, 0, &[], &[])).is_err());
+        assert!(bmp(&file(0x10000, 0x10000, 8, 1, &PALETTE, &[0, 1])).is_err());
+    }
+
+    #[test]
+    fn jpeg_precision_from_frame_header() {
+        // SOI, an APP0 segment, then SOF3 (lossless) with 12-bit samples
+        let data = [0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xc3, 0, 11, 12, 0, 1, 0, 1, 1, 1, 0x11, 0];
+        assert_eq!(jpeg_precision(&data), Some(12));
+        assert_eq!(jpeg_precision(&data[..8]), None);
+    }
+}
@@ -1,65 +1,12 @@
+use libuserbar::animation::{Animation, Frame, Keyframe, Marquee, MarqueeDirection, MarqueeMode};
 use std::fs::File;
 use std::io::BufWriter;
 use std::path::Path;
 
-fn readimg(name: &str) -> anyhow::Result<(Vec<u8>, usize, usize)> {
-    let decoder = png::Decoder::new(File::open(name)?);
-    let mut reader = decoder.read_info()?;
-    let mut buf = vec![0; reader.output_buffer_size()];
-    let h = reader.next_frame(&mut buf)?;
-    anyhow::ensure!(
-        h.color_type == png::ColorType::Rgba,
-        "Input image must be RGBA!"
-    );
-    anyhow::ensure!(
-        h.bit_depth == png::BitDepth::Eight,
-        "Input image must have 8 bits per channel!"
-    );
-    Ok((buf, h.width as usize, h.height as usize))
-}
-
-fn color_from_hex(hex: &str) -> anyhow::Result<libuserbar::Color> {
-    let hex = hex.strip_prefix('#').unwrap_or(hex);
-    match hex.len() {
-        3 => {
-            let r = u8::from_str_radix(&hex[0..1], 16)? * 0x11;
-            let g = u8::from_str_radix(&hex[1..2], 16)? * 0x11;
-            let b = u8::from_str_radix(&hex[2..3], 16)? * 0x11;
-            Ok(libuserbar::Color(r, g, b))
-        }
-        6 => {
-            let r = u8::from_str_radix(&hex[0..2], 16)?;
-            let g = u8::from_str_radix(&hex[2..4], 16)?;
-            let b = u8::from_str_radix(&hex[4..6], 16)?;
-            Ok(libuserbar::Color(r, g, b))
-        }
-        _ => anyhow::bail!("Expected RGB hex color to have 3 or 6 digits"),
-    }
-}
+mod decode;
 
 fn colora_from_hex(hex: &str) -> anyhow::Result<libuserbar::ColorA> {
-    let hex = hex.strip_prefix('#').unwrap_or(hex);
-    match hex.len() {
-        3 | 6 => {
-            let c = color_from_hex(hex)?;
-            Ok(libuserbar::ColorA(c.0, c.1, c.2, 255))
-        }
-        4 => {
-            let r = u8::from_str_radix(&hex[0..1], 16)? * 0x11;
-            let g = u8::from_str_radix(&hex[1..2], 16)? * 0x11;
-            let b = u8::from_str_radix(&hex[2..3], 16)? * 0x11;
-            let a = u8::from_str_radix(&hex[3..4], 16)? * 0x11;
-            Ok(libuserbar::ColorA(r, g, b, a))
-        }
-        8 => {
-            let r = u8::from_str_radix(&hex[0..2], 16)?;
-            let g = u8::from_str_radix(&hex[2..4], 16)?;
-            let b = u8::from_str_radix(&hex[4..6], 16)?;
-            let a = u8::from_str_radix(&hex[6..8], 16)?;
-            Ok(libuserbar::ColorA(r, g, b, a))
-        }
-        _ => anyhow::bail!("Expected RGBA hex color to have 3, 4, 6 or 8 digits"),
-    }
+    libuserbar::ColorA::from_hex(hex).map_err(anyhow::Error::msg)
 }
 
 fn parse_placement(s: &str) -> anyhow::Result<libuserbar::Placement> {
@@ -89,6 +36,323 @@ fn parse_placement(s: &str) -> anyhow::Result<libuserbar::Placement> {
     Ok(libuserbar::Placement { horz, vert })
 }
 
+// parses "N%" into a fraction.
+fn parse_fraction(s: &str) -> anyhow::Result<f32> {
+    let x = s.trim().strip_suffix('%').ok_or(anyhow::anyhow!("expected a percentage, got {s:?}"))?;
+    Ok(x.parse::<f32>()? / 100.)
+}
+
+// parses "X%,Y%"
+fn parse_fraction_pair(s: &str) -> anyhow::Result<(f32, f32)> {
+    let (x, y) = s.split_once(',').ok_or(anyhow::anyhow!("expected X%,Y%"))?;
+    Ok((parse_fraction(x)?, parse_fraction(y)?))
+}
+
+// parses "SPACE [PATH hue]", e.g. "oklch longer hue".
+fn parse_color_space(s: &str) -> anyhow::Result<(libuserbar::ColorSpace, libuserbar::HuePath)> {
+    use libuserbar::{ColorSpace, HuePath};
+    let words: Vec<_> = s.split_whitespace().collect();
+    let (space, hue) = match *words.as_slice() {
+        [space] => (space, None),
+        [space, path, "hue"] => (space, Some(path)),
+        _ => anyhow::bail!("expected \"in SPACE\" or \"in SPACE PATH hue\""),
+    };
+    let space = match space {
+        "srgb" => ColorSpace::Srgb,
+        "linear" | "srgb-linear" => ColorSpace::LinearRgb,
+        "oklab" => ColorSpace::Oklab,
+        "oklch" => ColorSpace::Oklch,
+        x => anyhow::bail!("unknown color space {x:?}"),
+    };
+    let hue = match hue {
+        None | Some("shorter") => HuePath::Shorter,
+        Some("longer") => HuePath::Longer,
+        Some("increasing") => HuePath::Increasing,
+        Some("decreasing") => HuePath::Decreasing,
+        Some(x) => anyhow::bail!("unknown hue path {x:?}"),
+    };
+    anyhow::ensure!(hue == HuePath::Shorter || space == ColorSpace::Oklch, "hue paths only apply to oklch");
+    Ok((space, hue))
+}
+
+// parses a CSS-like gradient: an optional direction followed by color stops, all separated by
+// commas. the direction is either "Ndeg", "to top/bottom/left/right" or "radial [circle] [at X% Y%]",
+// and defaults to top to bottom. it can be followed by "in SPACE [PATH hue]" to pick the color
+// space to mix in (srgb, linear, oklab or oklch). stops are "COLOR [POS%]"; stops without a
+// position are spread evenly between their neighbours, as in CSS.
+fn parse_gradient(s: &str) -> anyhow::Result<libuserbar::Gradient> {
+    let mut parts: Vec<&str> = s.split(',').map(str::trim).collect();
+    let (first, space) = match parts[0].strip_prefix("in ") {
+        Some(space) => ("", Some(space)),
+        None => match parts[0].split_once(" in ") {
+            Some((first, space)) => (first, Some(space)),
+            None => (parts[0], None),
+        },
+    };
+    let direction = if let Some(deg) = first.strip_suffix("deg") {
+        Some(libuserbar::Gradient::new(deg.trim().parse()?))
+    } else if let Some(side) = first.strip_prefix("to ") {
+        Some(libuserbar::Gradient::new(match side.trim() {
+            "top" => 0.,
+            "right" => 90.,
+            "bottom" => 180.,
+            "left" => 270.,
+            x => anyhow::bail!("unknown gradient direction {x:?}"),
+        }))
+    } else if let Some(rest) = first.strip_prefix("radial") {
+        let (shape, center) = rest.split_once("at").unwrap_or((rest, "50% 50%"));
+        let circle = match shape.trim() {
+            "" | "ellipse" => false,
+            "circle" => true,
+            x => anyhow::bail!("expected circle or ellipse, got {x:?}"),
+        };
+        let (x, y) = center.trim().split_once(' ').ok_or(anyhow::anyhow!("expected \"at X% Y%\""))?;
+        Some(libuserbar::Gradient::radial(parse_fraction(x)?, parse_fraction(y)?, circle))
+    } else if first.is_empty() {
+        Some(libuserbar::Gradient::new(180.))
+    } else {
+        None
+    };
+    let mut grad = match direction {
+        Some(g) => {
+            parts.remove(0);
+            g
+        }
+        None => libuserbar::Gradient::new(180.),
+    };
+    if let Some(space) = space {
+        (grad.space, grad.hue_path) = parse_color_space(space)?;
+    }
+    anyhow::ensure!(!parts.is_empty(), "gradient needs at least one color");
+
+    let mut stops: Vec<(libuserbar::ColorA, Option<f32>)> = Vec::new();
+    for part in parts {
+        let (color, pos) = part.split_once(' ').unwrap_or((part, ""));
+        let pos = if pos.trim().is_empty() { None } else { Some(parse_fraction(pos)?) };
+        stops.push((colora_from_hex(color)?, pos));
+    }
+    let n = stops.len();
+    stops[0].1.get_or_insert(0.);
+    stops[n - 1].1.get_or_insert(1.);
+    let mut prev = 0;
+    for i in 1..n {
+        if let Some(pos) = stops[i].1 {
+            // spread out the stops since the previous one that has a position
+            let start = stops[prev].1.unwrap();
+            for (j, stop) in stops[prev + 1..i].iter_mut().enumerate() {
+                stop.1 = Some(start + (pos - start) * (j + 1) as f32 / (i - prev) as f32);
+            }
+            prev = i;
+        }
+    }
+    let mut last = f32::NEG_INFINITY;
+    for (color, pos) in stops {
+        // like in CSS, a stop can't come before the previous one
+        last = last.max(pos.unwrap());
+        grad.add_stop(last, color);
+    }
+    Ok(grad)
+}
+
+fn parse_pattern_kind(s: &str) -> anyhow::Result<libuserbar::PatternKind> {
+    use libuserbar::PatternKind;
+    Ok(match s {
+        "diagonal" => PatternKind::Diagonal,
+        "anti-diagonal" => PatternKind::AntiDiagonal,
+        "horizontal" => PatternKind::Horizontal,
+        "vertical" => PatternKind::Vertical,
+        "crosshatch" => PatternKind::Crosshatch,
+        "grid" => PatternKind::Grid,
+        "dots" => PatternKind::Dots,
+        "checkerboard" => PatternKind::Checkerboard,
+        x => anyhow::bail!("unknown pattern {x:?}"),
+    })
+}
+
+fn parse_blend_mode(s: &str) -> anyhow::Result<libuserbar::layer::BlendMode> {
+    use libuserbar::layer::BlendMode;
+    Ok(match s {
+        "normal" => BlendMode::Normal,
+        "multiply" => BlendMode::Multiply,
+        "screen" => BlendMode::Screen,
+        "overlay" => BlendMode::Overlay,
+        "soft-light" => BlendMode::SoftLight,
+        "add" => BlendMode::Add,
+        "difference" => BlendMode::Difference,
+        x => anyhow::bail!("unknown blend mode {x:?}"),
+    })
+}
+
+// parses "SHAPE[:RADIUS]", e.g. "round:2". the radius defaults to 1.
+fn parse_outline(s: &str) -> anyhow::Result<(libuserbar::OutlineShape, usize)> {
+    use libuserbar::OutlineShape;
+    let (shape, radius) = s.split_once(':').unwrap_or((s, "1"));
+    let shape = match shape {
+        "none" => OutlineShape::None,
+        "diamond" => OutlineShape::Diamond,
+        "square" => OutlineShape::Square,
+        "round" => OutlineShape::Round,
+        _ => anyhow::bail!("expected none, diamond, square or round"),
+    };
+    Ok((shape, radius.parse()?))
+}
+
+// parses "X,Y"
+fn parse_offset(s: &str) -> anyhow::Result<(isize, isize)> {
+    let (x, y) = s.split_once(',').ok_or(anyhow::anyhow!("expected x,y"))?;
+    Ok((x.trim().parse()?, y.trim().parse()?))
+}
+
+// parses "FRAME:prop=value;prop=value;..."
+fn parse_keyframe(s: &str) -> anyhow::Result<Keyframe> {
+    let (frame, props) = s.split_once(':').ok_or(anyhow::anyhow!("expected frame number followed by ':'"))?;
+    let mut kf = Keyframe { frame: frame.trim().parse()?, ..Default::default() };
+    for prop in props.split(';').map(str::trim).filter(|x| !x.is_empty()) {
+        let (k, v) = prop.split_once('=').ok_or(anyhow::anyhow!("expected prop=value, got {prop:?}"))?;
+        match k.trim() {
+            "text-pos" => kf.text_placement = Some(parse_placement(v)?),
+            "text-color" => kf.text_color = Some(colora_from_hex(v)?),
+            "text-outline-color" => kf.text_outline_color = Some(colora_from_hex(v)?),
+            "grad-top" => kf.bg_top_color = Some(colora_from_hex(v)?),
+            "grad-bottom" => kf.bg_bottom_color = Some(colora_from_hex(v)?),
+            "gloss-color" | "ellipse-color" => kf.gloss_color = Some(colora_from_hex(v)?),
+            "gloss-center" => kf.gloss_center = Some(parse_fraction_pair(v)?),
+            "scan-phase" => kf.stripe_phase = Some(v.trim().parse()?),
+            "delay" => kf.delay = Some(v.trim().parse()?),
+            x => anyhow::bail!("unknown keyframe property {x:?}"),
+        }
+    }
+    Ok(kf)
+}
+
+// parses "prop=value;...;text=TEXT". the text has to come last, so that it can contain semicolons.
+// unspecified properties are taken from `base`.
+fn parse_text_item(s: &str, base: &libuserbar::TextItem) -> anyhow::Result<libuserbar::TextItem> {
+    let mut item = base.clone();
+    let mut rest = s;
+    while !rest.is_empty() {
+        if let Some(text) = rest.strip_prefix("text=") {
+            item.text = text.to_string();
+            return Ok(item);
+        }
+        let (prop, next) = rest.split_once(';').unwrap_or((rest, ""));
+        rest = next;
+        match prop.split_once('=').unwrap_or((prop, "")) {
+            ("pos", v) => item.placement = parse_placement(v)?,
+            ("color", v) => item.color = colora_from_hex(v)?,
+            ("outline", v) => item.outline_color = colora_from_hex(v)?,
+            ("outline-shape", v) => (item.outline.shape, item.outline.radius) = parse_outline(v)?,
+            ("outline-offset", v) => (item.outline.offset_x, item.outline.offset_y) = parse_offset(v)?,
+            ("over-ellipse", "") => item.over_ellipse = true,
+            ("under-ellipse", "") => item.over_ellipse = false,
+            ("markup", "") => item.markup = true,
+            ("no-markup", "") => item.markup = false,
+            ("", "") => {}
+            _ => anyhow::bail!("unknown text property {prop:?}"),
+        }
+    }
+    anyhow::bail!("missing text= in {s:?}")
+}
+
+fn parse_fit_mode(s: &str) -> anyhow::Result<libuserbar::FitMode> {
+    Ok(match s {
+        "none" => libuserbar::FitMode::None,
+        "contain" => libuserbar::FitMode::Contain,
+        "cover" => libuserbar::FitMode::Cover,
+        "stretch" => libuserbar::FitMode::Stretch,
+        "fit-height" => libuserbar::FitMode::FitHeight,
+        _ => anyhow::bail!("expected none, contain, cover, stretch or fit-height"),
+    })
+}
+
+fn parse_icon(s: &str) -> anyhow::Result<libuserbar::Icon> {
+    let auto = libuserbar::AxisPlacement { anchor: libuserbar::AxisAnchor::Auto, offset: 0 };
+    let mut placement = libuserbar::Placement { horz: auto, vert: auto };
+    let mut fit = libuserbar::FitMode::None;
+    let mut outline = libuserbar::Outline::new(libuserbar::OutlineShape::None, 0, 0, 0);
+    let mut outline_color = None;
+    let mut rest = s;
+    while !rest.is_empty() {
+        if let Some(file) = rest.strip_prefix("file=") {
+            let img = decode::read(file)?;
+            let image = libuserbar::BgImage::new(
+                img.width,
+                img.height,
+                img.frames[0].0.clone(),
+                placement,
+                fit,
+                libuserbar::Resample::Bilinear,
+            );
+            let mut icon = libuserbar::Icon::new(image);
+            // giving just a color gets the same outline text has by default
+            if outline.shape == libuserbar::OutlineShape::None && outline_color.is_some() {
+                outline = libuserbar::Outline::default();
+            }
+            icon.outline = outline;
+            icon.outline_color = outline_color.unwrap_or(icon.outline_color);
+            return Ok(icon);
+        }
+        let (prop, next) = rest.split_once(';').unwrap_or((rest, ""));
+        rest = next;
+        match prop.split_once('=').unwrap_or((prop, "")) {
+            ("pos", v) => placement = parse_placement(v)?,
+            ("fit", v) => fit = parse_fit_mode(v)?,
+            ("outline", v) => outline_color = Some(colora_from_hex(v)?),
+            ("outline-shape", v) => (outline.shape, outline.radius) = parse_outline(v)?,
+            ("outline-offset", v) => (outline.offset_x, outline.offset_y) = parse_offset(v)?,
+            ("", "") => {}
+            _ => anyhow::bail!("unknown icon property {prop:?}"),
+        }
+    }
+    anyhow::bail!("missing file= in {s:?}")
+}
+
+fn write_png(path: &Path, width: usize, height: usize, frames: &[Frame], loops: u32) -> anyhow::Result<()> {
+    let file = File::create(path)?;
+    let w = BufWriter::new(file);
+    let mut enc = png::Encoder::new(w, width as u32, height as u32);
+    enc.set_color(png::ColorType::Rgba);
+    enc.set_depth(png::BitDepth::Eight);
+    enc.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
+    enc.set_srgb(png::SrgbRenderingIntent::Perceptual);
+    if frames.len() > 1 {
+        enc.set_animated(frames.len() as u32, loops)?;
+    }
+    let mut writer = enc.write_header()?;
+    for frame in frames {
+        if frames.len() > 1 {
+            writer.set_frame_delay(frame.delay.min(u16::MAX as u32) as u16, 1000)?;
+        }
+        writer.write_image_data(&frame.data)?;
+    }
+    writer.finish()?;
+    Ok(())
+}
+
+fn write_gif(path: &Path, width: usize, height: usize, frames: &[Frame], loops: u32) -> anyhow::Result<()> {
+    let width: u16 = width.try_into().or(Err(anyhow::anyhow!("image too wide for GIF")))?;
+    let height: u16 = height.try_into().or(Err(anyhow::anyhow!("image too tall for GIF")))?;
+    let file = File::create(path)?;
+    let mut enc = gif::Encoder::new(BufWriter::new(file), width, height, &[])?;
+    // the loop count in the file is the number of *extra* plays, and 0 means forever
+    if frames.len() > 1 {
+        match loops {
+            0 => enc.set_repeat(gif::Repeat::Infinite)?,
+            1 => {}
+            n => enc.set_repeat(gif::Repeat::Finite((n - 1).min(u16::MAX as u32) as u16))?,
+        }
+    }
+    for frame in frames {
+        let mut data = frame.data.clone();
+        let mut f = gif::Frame::from_rgba_speed(width, height, &mut data, 10);
+        // gif delays are in centiseconds
+        f.delay = ((frame.delay + 5) / 10).min(u16::MAX as u32) as u16;
+        f.dispose = gif::DisposalMethod::Background;
+        enc.write_frame(&f)?;
+    }
+    Ok(())
+}
+
 fn main() -> anyhow::Result<()> {
     let mut opts = libuserbar::Options::new();
     let mut args = pico_args::Arguments::from_env();
@@ -96,35 +360,132 @@ fn main() -> anyhow::Result<()> {
         println!("usage: userbar [options]
 
 options:
-    --grad-top      Color of top of background gradient [default: #00f]
-    --grad-bottom   Color of bottom of background gradient [default: #8ff]
+    --grad-top      Color of top of background gradient, may be transparent [default: #00f]
+    --grad-bottom   Color of bottom of background gradient, may be transparent [default: #8ff]
+    --gradient      Background gradient in a CSS-like syntax, overriding --grad-top and
+                    --grad-bottom, e.g. \"90deg, #00f 0%, #0ff 60%, #fff 100%\" or
+                    \"radial circle at 50% 0%, #fff, #00f\". The direction can be Ndeg,
+                    to top/bottom/left/right or radial [circle] [at X% Y%], optionally
+                    followed by the color space to mix colors in: \"in srgb\" (default),
+                    linear, oklab or oklch, where oklch can be followed by a hue path
+                    (shorter, longer, increasing or decreasing), e.g.
+                    \"90deg in oklch longer hue, #f00, #00f\".
     -w, --width     Output width [default: 350]
     -h, --height    Output height [default: 19]
-    -o, --output    Filename of output (will be PNG format) [required]
-    -i, --bg-image  Image to use as a background [default: no image]
+    -o, --output    Filename of output (GIF if it ends in .gif, PNG otherwise) [required]
+    -i, --bg-image  Image to use as a background, in PNG, GIF, BMP, JPEG or QOI format. Animated
+                    GIFs play along with animated userbars [default: no image]
     --bg-pos        Placement of BG image [default: top-left corner]
+    --bg-fit        Scale the BG image: none, contain (fit inside), cover (fill, cropping
+                    the rest), stretch or fit-height [default: none]
+    --bg-resample   How to scale the BG image: nearest (for pixel art), bilinear or
+                    lanczos [default: bilinear]
+    --bg-repeat     Repeat the BG image around its placement: none, repeat-x, repeat-y,
+                    repeat (both) or mirror (both, flipping every other copy) [default: none]
+    --bg-colorize   Recolor the BG image with the background gradient's colors, dark parts
+                    getting the first color and light parts the last
+    --bg-grayscale  Same as --bg-saturation 0
+    --bg-saturation How colorful the BG image is, 0 for grayscale [default: 1]
+    --bg-tint       Color to mix into the BG image, as much as its alpha, e.g. #f808
+    --bg-brightness Multiply the BG image's colors by this [default: 1]
+    --bg-contrast   Multiply the BG image's contrast by this [default: 1]
+    --bg-opacity    Opacity of the BG image from 0 to 1 [default: 1]
+    --bg-fade       Fade the BG image's edges out over some pixels, as SIDE:PIXELS,... where
+                    SIDE is left, right, top or bottom, e.g. left:10,right:30
+    --bg-vignette   Fade the BG image out towards its corners in an oval, starting this far
+                    from its edges towards its middle, from 0 to 1 [default: 0]
+    --overlay-tile  Small image to repeat across the whole background, over the BG image
+    --overlay-pos   Placement of one of the copies of the tile [default: top-left corner]
+    --bg-blend, --overlay-blend, --text-blend, --gloss-blend, --scan-blend
+                    How the BG image, overlay tile, text, gloss or scanlines combine with
+                    what's below them: normal, multiply, screen, overlay, soft-light, add
+                    or difference [default: normal]
     -t, --text      Text to use [required]
     --text-pos      Placement of text [default: center-right]
     --text-color    Color of text [default: #fff]
     --text-outline-color  Color of text's outline [default: #000]
-    --no-ellipse    Disable the ellipse for the \"glare\" effect
-    --ellipse-color Color of the ellipse [default: #ffffff28]
+    --text-outline  Shape of text's outline, as SHAPE[:RADIUS]. Shapes are none, diamond,
+                    square and round [default: square:1]
+    --text-outline-offset  Move the outline by X,Y pixels. Use with a radius of 0 for a
+                    drop shadow, e.g. --text-outline square:0 --text-outline-offset 1,1
+    --font          BDF, .FON, .FNT, TrueType or OpenType font file to use for the text
+                    [default: built-in Visitor]
+    --font-size     Em size in pixels, for TrueType/OpenType fonts [default: 16]
+    --font-threshold  Draw TrueType/OpenType text without antialiasing, turning on pixels
+                    with at least this much coverage (0-255, 0 meaning any)
+    --no-ellipse    Disable the ellipse (gloss) for the \"glare\" effect
+    --ellipse-color Color of the middle of the gloss [default: #ffffff28]
+    --gloss         Shape of the gloss: ellipse, top-half (straight highlight over the top
+                    half), sweep (diagonal streak) or gradient (fades out towards the
+                    bottom) [default: ellipse]
+    --gloss-center  Center of the gloss as X%,Y% of the size [default depends on shape]
+    --gloss-size    Radii of an ellipse as X%,Y%, or the distance a band reaches to either
+                    side as Y% [default depends on shape]
+    --gloss-angle   Tilt of a band in degrees, clockwise [default depends on shape]
+    --gloss-falloff How the gloss fades out towards its edge: flat, linear or smooth
+                    [default depends on shape]
+    --markup        Parse BBCode-like markup in the text: [color=#f80]...[/color] and
+                    [outline=#000]...[/outline]. Use [[ for a literal [.
     --text-over-ellipse   Draw the text above the ellipse, instead of below
+    --add-text      Additional text, as \"prop=value;...;text=TEXT\". Can be given multiple
+                    times. Properties are pos, color, outline, outline-shape, outline-offset
+                    (as in --text-pos etc.),
+                    over-ellipse/under-ellipse and markup/no-markup (without a value); by
+                    default they're the same as the main text's. text= has to come last.
+    --icon          Small image to draw next to the text, as \"prop=value;...;file=PATH\". Can be
+                    given multiple times. Properties are pos (as in --text-pos), fit (as in
+                    --bg-fit), outline (color), outline-shape and outline-offset (as in
+                    --text-outline etc.). file= has to come last. Icons with auto horizontal
+                    position are lined up at the right end, and the text moves left of them.
     --no-border     Disable drawing a border
     --border-color  Color of border [default: #000]
+    --border-width  Width of border in pixels [default: 1]
+    --border-style  solid, double (two lines with a gap, each as wide as --border-width) or
+                    dashed [default: solid]
+    --border-dash   Length of dashes and gaps for dashed borders [default: 4]
+    --bevel         Colors of 1px lines inside the border, as LIGHT,DARK: LIGHT goes along
+                    the top and left, DARK along the bottom and right
+    --corner-radius Round the corners off by this many pixels, leaving them transparent
+                    [default: 0]
     --no-scan       Disable drawing \"scanlines\"
     --scan-color    Color of scanlines [default: #000000b4]
-    --scan-flip     Flip scanline direction
+    --scan-flip     Flip scanline direction, same as --scan-pattern anti-diagonal
+    --scan-pattern  One of diagonal, anti-diagonal, horizontal, vertical, crosshatch, grid,
+                    dots or checkerboard [default: diagonal]
     --scan-width    Width of scanline pattern [default: 4]
+    --scan-thickness  Width of each scanline, or size of each dot [default: 1]
+    --scan-phase    Move the pattern by this many pixels: sideways, or down for horizontal
+                    lines, or diagonally for grids, dots and checkerboards [default: 0]
+
+animation options (animations are written as APNG or GIF):
+    --frames        Number of frames [default: 1]
+    --frame-delay   Duration of each frame in milliseconds [default: 100]
+    --loops         How many times to play the animation, 0 for forever [default: 0]
+    --key           Keyframe, as \"FRAME:prop=value;...\". Can be given multiple times.
+                    Properties are interpolated between keyframes. Available properties:
+                    text-pos, text-color, text-outline-color, grad-top, grad-bottom,
+                    gloss-color, gloss-center (as in --gloss-center), scan-phase,
+                    delay (in ms). With --gradient, grad-top and grad-bottom are its
+                    first and last stops.
+    --marquee       Scroll the text horizontally. One of: loop (text wraps around),
+                    bounce (scrolls back and forth if the text doesn't fit).
+                    Replaces --frames and --text-pos's horizontal part.
+    --marquee-dir   Scrolling direction, left or right [default: left]
+    --marquee-speed Scrolling speed in pixels per frame [default: 1]
+    --marquee-gap   Space between repeats of the text in loop mode [default: 40]
+    --marquee-pause How long to stop at the ends in milliseconds [default: 1000]
 ");
         return Ok(());
     }
-    if let Some(v) = args.opt_value_from_fn("--grad-top", color_from_hex)? {
+    if let Some(v) = args.opt_value_from_fn("--grad-top", colora_from_hex)? {
         opts.bg_top_color = v;
     }
-    if let Some(v) = args.opt_value_from_fn("--grad-bottom", color_from_hex)? {
+    if let Some(v) = args.opt_value_from_fn("--grad-bottom", colora_from_hex)? {
         opts.bg_bottom_color = v;
     }
+    if let Some(v) = args.opt_value_from_fn("--gradient", parse_gradient)? {
+        opts.bg_gradient = Some(v);
+    }
     if let Some(v) = args.opt_value_from_fn(["-w", "--width"], str::parse::<usize>)? {
         opts.width = v;
     }
@@ -132,17 +493,20 @@ options:
         opts.height = v;
     }
     let outname: String = args.value_from_str(["-o", "--output"])?;
+    let auto = libuserbar::AxisPlacement { anchor: libuserbar::AxisAnchor::Auto, offset: 0 };
+    // animated GIFs, kept around to swap in the right frame for each frame of the userbar
+    let mut bg_frames = None;
     if let Some(v) = args.opt_value_from_str::<_, String>(["-i", "--bg-image"])? {
-        let (buf, width, height) = readimg(&v)?;
-        opts.bg_image = Some(libuserbar::BgImage {
-            width,
-            height,
-            data: buf,
-            placement: libuserbar::Placement {
-                horz: libuserbar::AxisPlacement { anchor: libuserbar::AxisAnchor::Auto, offset: 0 },
-                vert: libuserbar::AxisPlacement { anchor: libuserbar::AxisAnchor::Auto, offset: 0 },
-            },
-        });
+        let img = decode::read(&v)?;
+        opts.bg_image = Some(libuserbar::BgImage::new(
+            img.width,
+            img.height,
+            img.frames[0].0.clone(),
+            libuserbar::Placement { horz: auto, vert: auto },
+            libuserbar::FitMode::None,
+            libuserbar::Resample::Bilinear,
+        ));
+        bg_frames = Some(img).filter(|x| x.frames.len() > 1);
     }
     if let Some(v) = args.opt_value_from_fn("--bg-pos", parse_placement)? {
         opts.bg_image
@@ -150,33 +514,215 @@ options:
             .ok_or(anyhow::anyhow!("--bg-pos provided without --bg-image"))?
             .placement = v;
     }
+    if let Some(v) = args.opt_value_from_fn("--bg-fit", parse_fit_mode)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-fit provided without --bg-image"))?.fit = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-resample", |x| match x {
+        "nearest" => Ok(libuserbar::Resample::Nearest),
+        "bilinear" => Ok(libuserbar::Resample::Bilinear),
+        "lanczos" => Ok(libuserbar::Resample::Lanczos),
+        _ => Err(anyhow::anyhow!("expected nearest, bilinear or lanczos")),
+    })? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-resample provided without --bg-image"))?.resample = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-repeat", |x| match x {
+        "none" => Ok(libuserbar::RepeatMode::None),
+        "repeat-x" => Ok(libuserbar::RepeatMode::RepeatX),
+        "repeat-y" => Ok(libuserbar::RepeatMode::RepeatY),
+        "repeat" => Ok(libuserbar::RepeatMode::Repeat),
+        "mirror" => Ok(libuserbar::RepeatMode::Mirror),
+        _ => Err(anyhow::anyhow!("expected none, repeat-x, repeat-y, repeat or mirror")),
+    })? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-repeat provided without --bg-image"))?.repeat = v;
+    }
+    if args.contains("--bg-colorize") {
+        anyhow::ensure!(opts.bg_image.is_some(), "--bg-colorize provided without --bg-image");
+        opts.bg_image_colorize = true;
+    }
+    if args.contains("--bg-grayscale") {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-grayscale provided without --bg-image"))?.filter.saturation = 0.;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-saturation", str::parse::<f32>)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-saturation provided without --bg-image"))?.filter.saturation = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-tint", colora_from_hex)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-tint provided without --bg-image"))?.filter.tint = Some(v);
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-brightness", str::parse::<f32>)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-brightness provided without --bg-image"))?.filter.brightness = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-contrast", str::parse::<f32>)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-contrast provided without --bg-image"))?.filter.contrast = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-opacity", str::parse::<f32>)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-opacity provided without --bg-image"))?.filter.opacity = v;
+    }
+    if let Some(v) = args.opt_value_from_str::<_, String>("--bg-fade")? {
+        let img = opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-fade provided without --bg-image"))?;
+        for part in v.split(',') {
+            let (side, len) = part.split_once(':').ok_or(anyhow::anyhow!("expected --bg-fade SIDE:PIXELS,..."))?;
+            let len = len.trim().parse()?;
+            match side.trim() {
+                "left" => img.fade.left = len,
+                "right" => img.fade.right = len,
+                "top" => img.fade.top = len,
+                "bottom" => img.fade.bottom = len,
+                x => anyhow::bail!("unknown side {x:?}, expected left, right, top or bottom"),
+            }
+        }
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-vignette", str::parse::<f32>)? {
+        opts.bg_image.as_mut().ok_or(anyhow::anyhow!("--bg-vignette provided without --bg-image"))?.fade.vignette = v.clamp(0., 1.);
+    }
+    let mut tile_frames = None;
+    if let Some(v) = args.opt_value_from_str::<_, String>("--overlay-tile")? {
+        let img = decode::read(&v)?;
+        opts.overlay_tile = Some(libuserbar::BgImage::new(
+            img.width,
+            img.height,
+            img.frames[0].0.clone(),
+            libuserbar::Placement { horz: auto, vert: auto },
+            libuserbar::FitMode::None,
+            libuserbar::Resample::Bilinear,
+        ));
+        tile_frames = Some(img).filter(|x| x.frames.len() > 1);
+    }
+    if let Some(v) = args.opt_value_from_fn("--overlay-pos", parse_placement)? {
+        opts.overlay_tile
+            .as_mut()
+            .ok_or(anyhow::anyhow!("--overlay-pos provided without --overlay-tile"))?
+            .placement = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--bg-blend", parse_blend_mode)? {
+        opts.bg_image_blend = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--overlay-blend", parse_blend_mode)? {
+        opts.overlay_tile_blend = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--text-blend", parse_blend_mode)? {
+        opts.text_blend = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--gloss-blend", parse_blend_mode)? {
+        opts.gloss_blend = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--scan-blend", parse_blend_mode)? {
+        opts.diag_stripes_blend = v;
+    }
 
     let text: String = args.value_from_str(["-t", "--text"])?;
     opts.text = text;
+    opts.text_markup = args.contains("--markup");
     if let Some(v) = args.opt_value_from_fn("--text-pos", parse_placement)? {
         opts.text_placement = v;
     }
 
+    let font_size = args.opt_value_from_fn("--font-size", str::parse::<f32>)?;
+    let font_threshold = args.opt_value_from_fn("--font-threshold", str::parse::<u8>)?;
+    if let Some(v) = args.opt_value_from_str::<_, String>("--font")? {
+        let data = std::fs::read(v)?;
+        if libuserbar::font::TrueTypeFont::detect(&data) {
+            let font = libuserbar::font::TrueTypeFont::new(data, font_size.unwrap_or(16.), font_threshold)?;
+            opts.font = Some(std::sync::Arc::new(font));
+        } else {
+            anyhow::ensure!(
+                font_size.is_none() && font_threshold.is_none(),
+                "--font-size and --font-threshold only apply to TrueType/OpenType fonts"
+            );
+            opts.font = Some(std::sync::Arc::new(libuserbar::font::BitmapFont::from_bytes(&data)?));
+        }
+    }
     if let Some(v) = args.opt_value_from_fn("--text-color", colora_from_hex)? {
         opts.text_color = v;
     }
     if let Some(v) = args.opt_value_from_fn("--text-outline-color", colora_from_hex)? {
         opts.text_outline_color = v;
     }
+    if let Some(v) = args.opt_value_from_fn("--text-outline", parse_outline)? {
+        (opts.text_outline.shape, opts.text_outline.radius) = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--text-outline-offset", parse_offset)? {
+        (opts.text_outline.offset_x, opts.text_outline.offset_y) = v;
+    }
     if args.contains("--no-ellipse") {
-        opts.ellipse_color = None;
+        opts.gloss = None;
     } else {
+        let mut gloss = opts.gloss.unwrap();
+        if let Some(v) = args.opt_value_from_fn("--gloss", |x| match x {
+            "ellipse" => Ok(libuserbar::Gloss::ellipse as fn(_) -> _),
+            "top-half" => Ok(libuserbar::Gloss::top_half),
+            "sweep" => Ok(libuserbar::Gloss::sweep),
+            "gradient" => Ok(libuserbar::Gloss::vertical_gradient),
+            _ => Err(anyhow::anyhow!("expected ellipse, top-half, sweep or gradient")),
+        })? {
+            gloss = v(gloss.color);
+        }
         if let Some(v) = args.opt_value_from_fn("--ellipse-color", colora_from_hex)? {
-            opts.ellipse_color = Some(v);
+            gloss.color = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--gloss-center", parse_fraction_pair)? {
+            (gloss.center_x, gloss.center_y) = v;
+        }
+        if let Some(v) = args.opt_value_from_str::<_, String>("--gloss-size")? {
+            match v.split_once(',') {
+                Some(_) => (gloss.radius_x, gloss.radius_y) = parse_fraction_pair(&v)?,
+                None => gloss.radius_y = parse_fraction(&v)?,
+            }
+        }
+        if let Some(v) = args.opt_value_from_fn("--gloss-angle", str::parse::<f32>)? {
+            gloss.angle = v;
         }
+        if let Some(v) = args.opt_value_from_fn("--gloss-falloff", |x| match x {
+            "flat" => Ok(libuserbar::GlossFalloff::Flat),
+            "linear" => Ok(libuserbar::GlossFalloff::Linear),
+            "smooth" => Ok(libuserbar::GlossFalloff::Smooth),
+            _ => Err(anyhow::anyhow!("expected flat, linear or smooth")),
+        })? {
+            gloss.falloff = v;
+        }
+        opts.gloss = Some(gloss);
         if args.contains("--text-over-ellipse") {
             opts.text_over_ellipse = true;
         }
     }
+    let main_text = opts.main_text();
+    for spec in args.values_from_str::<_, String>("--add-text")? {
+        let item = parse_text_item(&spec, &main_text).map_err(|e| anyhow::anyhow!("failed to parse '{spec}': {e}"))?;
+        opts.extra_text.push(item);
+    }
+    for spec in args.values_from_str::<_, String>("--icon")? {
+        let icon = parse_icon(&spec).map_err(|e| anyhow::anyhow!("failed to parse '{spec}': {e}"))?;
+        opts.icons.push(icon);
+    }
     if args.contains("--no-border") {
-        opts.border_color = None;
-    } else if let Some(v) = args.opt_value_from_fn("--border-color", colora_from_hex)? {
-        opts.border_color = Some(v);
+        opts.border = None;
+    } else {
+        let mut border = opts.border.unwrap();
+        if let Some(v) = args.opt_value_from_fn("--border-color", colora_from_hex)? {
+            border.color = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--border-width", str::parse::<usize>)? {
+            border.width = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--border-style", |x| match x {
+            "solid" => Ok(libuserbar::BorderStyle::Solid),
+            "double" => Ok(libuserbar::BorderStyle::Double),
+            "dashed" => Ok(libuserbar::BorderStyle::Dashed),
+            _ => Err(anyhow::anyhow!("expected solid, double or dashed")),
+        })? {
+            border.style = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--border-dash", str::parse::<usize>)? {
+            border.dash_length = v;
+        }
+        if let Some(v) = args.opt_value_from_str::<_, String>("--bevel")? {
+            let (light, dark) = v.split_once(',').ok_or(anyhow::anyhow!("expected --bevel LIGHT,DARK"))?;
+            border.bevel_light = Some(colora_from_hex(light)?);
+            border.bevel_dark = Some(colora_from_hex(dark)?);
+        }
+        opts.border = Some(border);
+    }
+    if let Some(v) = args.opt_value_from_fn("--corner-radius", str::parse::<f32>)? {
+        opts.corner_radius = v;
     }
 
     if args.contains("--no-scan") {
@@ -188,15 +734,75 @@ options:
             awawa.color = v;
         }
         if args.contains("--scan-flip") {
-            awawa.on_main_diagonal = true;
+            awawa.kind = libuserbar::PatternKind::AntiDiagonal;
+        }
+        if let Some(v) = args.opt_value_from_fn("--scan-pattern", parse_pattern_kind)? {
+            awawa.kind = v;
         }
         if let Some(v) = args.opt_value_from_fn("--scan-width", str::parse::<usize>)? {
             awawa.spacing = v;
         }
+        if let Some(v) = args.opt_value_from_fn("--scan-thickness", str::parse::<usize>)? {
+            awawa.thickness = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--scan-phase", str::parse::<usize>)? {
+            awawa.phase = v;
+        }
         opts.diag_stripes = Some(awawa);
     }
+
+    let mut anim = Animation::new(1, 100);
+    if let Some(v) = args.opt_value_from_fn("--frames", str::parse::<usize>)? {
+        anyhow::ensure!(v > 0, "--frames must be at least 1");
+        anim.frame_count = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--frame-delay", str::parse::<u32>)? {
+        anim.frame_delay = v;
+    }
+    if let Some(v) = args.opt_value_from_fn("--loops", str::parse::<u32>)? {
+        anim.loop_count = v;
+    }
+    let keyframes = args.values_from_fn("--key", parse_keyframe)?;
+
+    let marquee_mode = args.opt_value_from_fn("--marquee", |x| match x {
+        "loop" => Ok(MarqueeMode::Loop),
+        "bounce" => Ok(MarqueeMode::Bounce),
+        _ => Err(anyhow::anyhow!("expected loop or bounce")),
+    })?;
+    if let Some(mode) = marquee_mode {
+        anyhow::ensure!(anim.frame_count == 1, "--frames can't be used with --marquee");
+        let mut marquee = Marquee {
+            mode,
+            direction: MarqueeDirection::Left,
+            speed: 1,
+            gap: 40,
+            frame_delay: anim.frame_delay,
+            pause: 1000,
+        };
+        if let Some(v) = args.opt_value_from_fn("--marquee-dir", |x| match x {
+            "left" => Ok(MarqueeDirection::Left),
+            "right" => Ok(MarqueeDirection::Right),
+            _ => Err(anyhow::anyhow!("expected left or right")),
+        })? {
+            marquee.direction = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--marquee-speed", str::parse::<usize>)? {
+            marquee.speed = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--marquee-gap", str::parse::<usize>)? {
+            marquee.gap = v;
+        }
+        if let Some(v) = args.opt_value_from_fn("--marquee-pause", str::parse::<u32>)? {
+            marquee.pause = v;
+        }
+        let loop_count = anim.loop_count;
+        (opts, anim) = marquee.build(&opts);
+        anim.loop_count = loop_count;
+    }
+    anim.keyframes.extend(keyframes);
+
     let rest = args.finish();
-    if rest.len() > 0 {
+    if !rest.is_empty() {
         anyhow::bail!(
             "Unrecognized options: {}",
             rest.iter()
@@ -206,16 +812,34 @@ options:
         )
     }
 
-    let out = libuserbar::generate(&opts);
+    let frames = if bg_frames.is_none() && tile_frames.is_none() {
+        libuserbar::animation::generate_frames(&opts, &anim)
+    } else {
+        // still userbars just use the first frame of animated images
+        let mut time = 0;
+        (0..anim.frame_count)
+            .map(|i| {
+                let mut o = anim.frame_options(&opts, i);
+                if anim.frame_count > 1 {
+                    if let (Some(img), Some(frames)) = (o.bg_image.as_mut(), &bg_frames) {
+                        img.data = frames.frame_at(time).to_vec();
+                    }
+                    if let (Some(img), Some(frames)) = (o.overlay_tile.as_mut(), &tile_frames) {
+                        img.data = frames.frame_at(time).to_vec();
+                    }
+                }
+                let delay = anim.frame_delay(i);
+                time += delay;
+                Frame { delay, data: libuserbar::generate(&o) }
+            })
+            .collect()
+    };
+    anyhow::ensure!(!frames.is_empty(), "the animation has no frames");
 
     let path = Path::new(&outname);
-    let file = File::create(path)?;
-    let w = BufWriter::new(file);
-    let mut enc = png::Encoder::new(w, opts.width as u32, opts.height as u32);
-    enc.set_color(png::ColorType::Rgb);
-    enc.set_depth(png::BitDepth::Eight);
-    enc.set_adaptive_filter(png::AdaptiveFilterType::Adaptive);
-    enc.set_srgb(png::SrgbRenderingIntent::Perceptual);
-    enc.write_header()?.write_image_data(&out)?;
-    Ok(())
+    if path.extension().is_some_and(|x| x.eq_ignore_ascii_case("gif")) {
+        write_gif(path, opts.width, opts.height, &frames, anim.loop_count)
+    } else {
+        write_png(path, opts.width, opts.height, &frames, anim.loop_count)
+    }
 }